@@ -0,0 +1,112 @@
+use gstreamer as gst;
+use lazy_static::lazy_static;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long the cache of a registry is kept without being written to.
+const MAX_UNUSED: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+lazy_static! {
+    /// A directory per GStreamer version, plugin path and registry state, so
+    /// that a change to any of them starts the cache afresh.
+    static ref DIR: Option<PathBuf> = {
+        let base = cache_home()?;
+        let mut hasher = DefaultHasher::new();
+
+        gst::version_string().as_str().hash(&mut hasher);
+        std::env::var("GST_PLUGIN_PATH").ok().hash(&mut hasher);
+        std::env::var("GST_PLUGIN_PATH_1_0").ok().hash(&mut hasher);
+        registry_modified(&base).hash(&mut hasher);
+
+        Some(
+            base.join("gst-completion")
+                .join(format!("{:016x}", hasher.finish())),
+        )
+    };
+}
+
+fn cache_home() -> Option<PathBuf> {
+    match std::env::var("XDG_CACHE_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".cache")),
+    }
+}
+
+/// When the registry was last written. It's kept per architecture next to
+/// ours unless the environment points elsewhere.
+fn registry_modified(base: &Path) -> Option<SystemTime> {
+    let files = match std::env::var("GST_REGISTRY_1_0").or_else(|_| std::env::var("GST_REGISTRY")) {
+        Ok(path) => vec![PathBuf::from(path)],
+        Err(_) => fs::read_dir(base.join("gstreamer-1.0"))
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| x.starts_with("registry."))
+            })
+            .collect(),
+    };
+
+    files
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok()?.modified().ok())
+        .max()
+}
+
+/// Contents of the cache entry `name`, none if it was never written for the
+/// current registry.
+pub fn read(name: &str) -> Option<String> {
+    fs::read_to_string(DIR.as_ref()?.join(name)).ok()
+}
+
+/// Writes the cache entry `name`, clearing out entries for registries gone
+/// by.
+pub fn write(name: &str, contents: &str) {
+    let dir = match DIR.as_ref() {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    // Another shell may be using a different plugin path, so only entries
+    // nothing has been written to for a while are cleared out.
+    if !dir.exists() {
+        if let Some(Ok(entries)) = dir.parent().map(fs::read_dir) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let unused = entry
+                    .metadata()
+                    .and_then(|x| x.modified())
+                    .ok()
+                    .and_then(|x| x.elapsed().ok())
+                    .is_some_and(|x| x > MAX_UNUSED);
+
+                if unused {
+                    let _ = fs::remove_dir_all(entry.path());
+                }
+            }
+        }
+    }
+
+    write_file(&dir.join(name), contents);
+}
+
+/// Writes `path` aside first and moves it in place, so that a completion
+/// running alongside never reads half of it. Failing to is no worse than
+/// going without the file.
+pub fn write_file(path: &Path, contents: &str) {
+    let tmp = path.with_extension("tmp");
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    if fs::write(&tmp, contents).is_ok() {
+        let _ = fs::rename(&tmp, path);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::gstreamer;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+/// How long a completion waits on the daemon before doing without it.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The request variable carrying the client's working directory, which
+/// relative paths complete from.
+const CWD: &str = "CWD";
+
+/// Variables the factories are listed by, once. A request asking for another
+/// list is left to the client.
+const LIST_VARS: [&str; 2] = ["GST_COMPLETION_RANK", "GST_COMPLETION_TYPES"];
+
+/// Whether a completion depends on the variable, so the client sends it.
+fn is_forwarded(key: &str) -> bool {
+    key.starts_with("COMP_") || key.starts_with("GST_COMPLETION_") || key == "HOME"
+}
+
+fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("gst-completion.sock"))
+}
+
+/// `KEY\0VALUE\0` for each variable.
+fn encode(vars: &[(String, String)]) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    for (key, value) in vars {
+        bytes.extend(key.as_bytes());
+        bytes.push(0);
+        bytes.extend(value.as_bytes());
+        bytes.push(0);
+    }
+
+    bytes
+}
+
+/// The variables of a request, only those a completion depends on.
+fn decode(bytes: &[u8]) -> Vec<(String, String)> {
+    let fields = String::from_utf8_lossy(bytes);
+    let mut fields = fields.split('\0');
+    let mut vars = vec![];
+
+    while let (Some(key), Some(value)) = (fields.next(), fields.next()) {
+        if is_forwarded(key) || key == CWD {
+            vars.push((key.to_owned(), value.to_owned()));
+        }
+    }
+
+    vars
+}
+
+/// Has a running daemon complete the line in the environment. None if there
+/// is no daemon, or it doesn't answer in time.
+pub fn request() -> Option<Vec<String>> {
+    let mut stream = UnixStream::connect(socket_path()?).ok()?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+
+    let mut vars = std::env::vars()
+        .filter(|(key, _)| is_forwarded(key))
+        .collect::<Vec<_>>();
+    vars.push((
+        CWD.to_owned(),
+        std::env::current_dir().ok()?.to_str()?.to_owned(),
+    ));
+
+    stream.write_all(&encode(&vars)).ok()?;
+    stream.shutdown(Shutdown::Write).ok()?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+
+    // Without the status line the daemon hung up, restarting most likely, or
+    // has another list of factories.
+    let suggestions = response.strip_prefix("ok\n")?;
+    Some(suggestions.lines().map(str::to_owned).collect())
+}
+
+fn plugins_modified(dirs: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    dirs.iter()
+        .map(|dir| std::fs::metadata(dir).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+/// Answers completion requests with the registry loaded once, `complete`
+/// working from the environment of each request. Starts over when plugins
+/// are added or removed, so that they're picked up.
+pub fn serve(complete: fn() -> Vec<String>) {
+    let path = match socket_path() {
+        Some(path) => path,
+        None => return eprintln!("XDG_RUNTIME_DIR is not set"),
+    };
+
+    // Left behind by a daemon that didn't get to clean up.
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => return eprintln!("{}: {}", path.display(), err),
+    };
+
+    gstreamer::warm_up();
+
+    let list = LIST_VARS
+        .iter()
+        .map(|x| std::env::var(x).ok())
+        .collect::<Vec<_>>();
+    let dirs = gstreamer::plugin_dirs();
+    let modified = plugins_modified(&dirs);
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        if plugins_modified(&dirs) != modified {
+            drop(stream);
+
+            let err = Command::new(std::env::current_exe().unwrap_or_default())
+                .args(std::env::args_os().skip(1))
+                .exec();
+
+            return eprintln!("couldn't restart: {}", err);
+        }
+
+        let mut request = vec![];
+        let _ = stream.set_read_timeout(Some(TIMEOUT));
+
+        if stream.read_to_end(&mut request).is_err() {
+            continue;
+        }
+
+        let vars = decode(&request);
+        let requested = LIST_VARS
+            .iter()
+            .map(|x| {
+                vars.iter()
+                    .find(|(key, _)| key == x)
+                    .map(|(_, value)| value.clone())
+            })
+            .collect::<Vec<_>>();
+
+        // Hung up on, the client completes with the list it asked for.
+        if requested != list {
+            continue;
+        }
+
+        for (key, _) in std::env::vars().filter(|(key, _)| is_forwarded(key)) {
+            std::env::remove_var(key);
+        }
+
+        for (key, value) in vars {
+            if key == CWD {
+                let _ = std::env::set_current_dir(value);
+            } else {
+                std::env::set_var(key, value);
+            }
+        }
+
+        let mut response = "ok\n".to_owned();
+
+        for suggestion in complete() {
+            response.push_str(&suggestion);
+            response.push('\n');
+        }
+
+        let _ = stream.write_all(response.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test0() {
+        let vars = vec![
+            (
+                "COMP_LINE".to_owned(),
+                "gst-launch-1.0 videotestsrc ! ".to_owned(),
+            ),
+            ("COMP_POINT".to_owned(), "30".to_owned()),
+            ("PATH".to_owned(), "/usr/bin".to_owned()),
+            ("GST_COMPLETION_FUZZY".to_owned(), "1".to_owned()),
+            ("HOME".to_owned(), "/root".to_owned()),
+            (CWD.to_owned(), "/tmp".to_owned()),
+        ];
+
+        let mut forwarded = vars.clone();
+        forwarded.remove(2);
+
+        assert_eq!(decode(&encode(&vars)), forwarded);
+        assert!(decode(b"COMP_LINE").is_empty());
+    }
+}
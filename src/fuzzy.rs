@@ -0,0 +1,125 @@
+/// Whether names match anywhere rather than by prefix, as
+/// `GST_COMPLETION_FUZZY=1` asks.
+pub fn enabled() -> bool {
+    std::env::var("GST_COMPLETION_FUZZY").is_ok_and(|x| !x.is_empty() && x != "0")
+}
+
+/// How well `name` matches `pattern`, the higher the better, none if it
+/// doesn't. A prefix goes first, then a substring, earlier and at a word
+/// the better, then pieces of the pattern found apart, like `h264dec` in
+/// `avdec_h264`. Shorter names go first among equal matches.
+fn score(pattern: &str, name: &str) -> Option<i64> {
+    let len = name.len() as i64;
+
+    if name.starts_with(pattern) {
+        return Some(3000 - len);
+    }
+
+    if let Some(i) = name.find(pattern) {
+        let word = name[..i].ends_with(|c: char| !c.is_ascii_alphanumeric());
+        return Some(2000 + if word { 100 } else { 0 } - i as i64 - len);
+    }
+
+    let pattern = pattern.replace(['_', '-'], "");
+    let mut haystack = name.to_owned();
+    let mut rest = pattern.as_str();
+    let mut pieces = 0;
+
+    // The longest piece left is taken each time, and can't be found again.
+    while !rest.is_empty() {
+        let n = (2..=rest.len())
+            .rev()
+            .filter(|&n| rest.is_char_boundary(n))
+            .find(|&n| haystack.contains(&rest[..n]))?;
+
+        haystack = haystack.replacen(&rest[..n], "\0", 1);
+        rest = &rest[n..];
+        pieces += 1;
+    }
+
+    Some(1000 - 100 * pieces - len)
+}
+
+/// The items whose name matches `pattern`, the best matches first and in the
+/// order they came otherwise. Only prefixes match, all alike, unless fuzzy
+/// matching is enabled.
+pub fn filter<T, I, F>(pattern: Option<&str>, items: I, name: F) -> Vec<T>
+where
+    I: IntoIterator<Item = T>,
+    F: Fn(&T) -> &str,
+{
+    let pattern = pattern.unwrap_or("");
+    let fuzzy = enabled();
+
+    let mut matches = items
+        .into_iter()
+        .filter_map(|x| {
+            let score = if fuzzy {
+                score(pattern, name(&x))?
+            } else if name(&x).starts_with(pattern) {
+                0
+            } else {
+                return None;
+            };
+
+            Some((score, x))
+        })
+        .collect::<Vec<_>>();
+
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, x)| x).collect()
+}
+
+/// Bash replaces the word with what the suggestions have in common, which
+/// for fuzzy matches may not start with the word at all. The word is then
+/// suggested too, so that what's in common is mostly nothing and bash keeps
+/// the word as typed. A single match still replaces it.
+pub fn keep_word(word: &str, mut suggestions: Vec<String>) -> Vec<String> {
+    let first = suggestions.first().map_or("", String::as_str);
+    let common = suggestions.iter().skip(1).fold(first, |common, x| {
+        let n = common
+            .char_indices()
+            .zip(x.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(x.len()), |((i, _), _)| i);
+
+        &common[..n]
+    });
+
+    if suggestions.len() > 1 && !common.is_empty() && !common.starts_with(word) {
+        suggestions.push(word.to_owned());
+    }
+
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test0() {
+        assert!(score("avdec", "avdec_h264") > score("264", "avdec_h264"));
+        assert!(score("264", "avdec_h264").is_some());
+        assert!(score("h264dec", "avdec_h264").is_some());
+        assert!(score("avdech264", "avdec_h264").is_some());
+        assert!(score("264", "avdec_h264") > score("h264dec", "avdec_h264"));
+        assert!(score("rate", "bitrate") > score("rate", "framerate"));
+        assert!(score("h264h264", "avdec_h264").is_none());
+        assert!(score("xyz", "avdec_h264").is_none());
+
+        // A piece must be two characters at least.
+        assert!(score("h264x", "avdec_h264").is_none());
+    }
+
+    #[test]
+    fn test1() {
+        let suggestions = vec!["bitrate".to_owned(), "framerate".to_owned()];
+        assert_eq!(keep_word("rate", suggestions.clone()), suggestions);
+
+        let suggestions = vec!["avdec_h264".to_owned(), "avdec_h265".to_owned()];
+        assert_eq!(keep_word("avdec_", suggestions.clone()), suggestions);
+        assert_eq!(keep_word("26", suggestions.clone()).last().unwrap(), "26");
+        assert_eq!(keep_word("26", suggestions[..1].to_vec()), suggestions[..1]);
+    }
+}
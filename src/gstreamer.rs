@@ -1,202 +1,1019 @@
+use crate::cache;
+use crate::fuzzy;
+use crate::history;
+use crate::property::PropertyInfo;
+use gst::glib::translate::IntoGlib;
 use gst::prelude::*;
-use gst::{Caps, Element, ElementFactory};
+use gst::{glib, Caps, ElementFactory};
 use gstreamer as gst;
+
+pub use gst::PadDirection;
 use lazy_static::lazy_static;
+use std::collections::HashMap;
 use std::iter::Iterator;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+
+/// Most converters put in front of an element that can't take the caps.
+const CHAIN_HOPS: usize = 2;
+
+/// Most elements the planner puts between the source and the sink.
+const PLAN_HOPS: usize = 5;
+
+/// Pipelines the planner prints.
+const PLAN_RESULTS: usize = 5;
+
+/// Most pipelines the planner carries on from at each hop, the highest
+/// ranked kept.
+const PLAN_FRONTIER: usize = 1000;
+
+/// What a primary rank is worth in an element's relevance, the other things
+/// that make an element relevant weighing about as much.
+const RANK_WEIGHT: i64 = 256;
+
+/// Uses of a link past which it doesn't get any more relevant.
+const MAX_USES: u32 = 16;
 
-const LIST_FAST: bool = false;
+/// Klasses of elements that usually follow another's, like a parser after a
+/// demuxer and a decoder after a parser.
+const FOLLOWS: [(&str, &str); 11] = [
+    ("Source", "Demuxer"),
+    ("Demuxer", "Parser"),
+    ("Demuxer", "Decoder"),
+    ("Depayloader", "Parser"),
+    ("Parser", "Decoder"),
+    ("Decoder", "Converter"),
+    ("Converter", "Encoder"),
+    ("Converter", "Sink"),
+    ("Encoder", "Parser"),
+    ("Encoder", "Muxer"),
+    ("Muxer", "Sink"),
+];
 
 lazy_static! {
-    static ref LIST: Vec<ElementFactory> = {
-        gst::init().unwrap();
-        let mut v = vec![];
-
-        if LIST_FAST {
-            for rank in [
-                gst::Rank::PRIMARY,
-                gst::Rank::SECONDARY,
-                gst::Rank::MARGINAL,
-                gst::Rank::NONE,
-            ] {
-                v.extend(
-                    gst::ElementFactory::factories_with_type(gst::ElementFactoryType::ANY, rank)
-                    .into_iter()
-                )
+    static ref LIST: Vec<Factory> = {
+        gst::init().unwrap();
+
+        let rank = std::env::var("GST_COMPLETION_RANK")
+            .ok()
+            .and_then(|x| parse_rank(&x))
+            .unwrap_or(0);
+        let kind = std::env::var("GST_COMPLETION_TYPES")
+            .ok()
+            .and_then(|x| parse_factory_type(&x));
+
+        // Each choice of factories is a list of its own.
+        let name = match kind {
+            Some(kind) => format!("factories-{}-{:x}", rank, kind.bits()),
+            None => format!("factories-{}-all", rank),
+        };
+
+        match cache::read(&name).and_then(|x| Factory::parse_list(&x)) {
+            Some(list) => list,
+            None => {
+                let list = list_factories(rank, kind)
+                    .iter()
+                    .map(Factory::new)
+                    .collect::<Vec<_>>();
+                let contents = list.iter().map(Factory::to_cache).collect::<String>();
+
+                cache::write(&name, &contents);
+                list
             }
-        } else {
-            for elem in String::from_utf8(Command::new("gst-inspect-1.0").output().unwrap().stdout)
-                .unwrap()
-                    .split("\n")
-                    .filter_map(|x| {
-                        if x.is_empty() {
-                            None
-                        } else {
-                            let mut tokens = x.split(":");
-                            if tokens.next().unwrap().contains(" ") {
-                                None
-                            } else {
-                                Some(tokens.next().unwrap().trim().split(" ").next().unwrap())
-                            }
-                        }
-                    })
-            {
-                if let Some(element) = gst::ElementFactory::find(elem) {
-                    v.push(element)
+        }
+    };
+
+    static ref INDEX: CapsIndex = CapsIndex::new(&LIST);
+}
+
+/// Factories ranked `rank` or higher, of the kinds in `kind` if given, by
+/// name. Not given, even those of no kind at all are listed, like `queue`,
+/// which a type of `ANY` would leave out.
+fn list_factories(rank: i32, kind: Option<gst::ElementFactoryType>) -> Vec<ElementFactory> {
+    let mut list = gst::Registry::get()
+        .features(ElementFactory::static_type())
+        .into_iter()
+        .filter_map(|x| x.downcast::<ElementFactory>().ok())
+        .filter(|x| x.rank().into_glib() >= rank)
+        .filter(|x| kind.map_or(true, |kind| x.has_type(kind)))
+        .collect::<Vec<_>>();
+
+    list.sort_by_key(|x| x.name().to_string());
+    list
+}
+
+/// A rank by name, as gst-inspect-1.0 shows them, or number.
+fn parse_rank(s: &str) -> Option<i32> {
+    let rank = match s.trim().to_lowercase().as_str() {
+        "none" => gst::Rank::NONE,
+        "marginal" => gst::Rank::MARGINAL,
+        "secondary" => gst::Rank::SECONDARY,
+        "primary" => gst::Rank::PRIMARY,
+        x => return x.parse().ok(),
+    };
+
+    Some(rank.into_glib())
+}
+
+/// Comma-separated kinds of factories, like `decoder,media-video`. None if
+/// none is known.
+fn parse_factory_type(s: &str) -> Option<gst::ElementFactoryType> {
+    let kind = s
+        .split(',')
+        .map(|x| match x.trim().to_lowercase().as_str() {
+            "decoder" => gst::ElementFactoryType::DECODER,
+            "encoder" => gst::ElementFactoryType::ENCODER,
+            "sink" => gst::ElementFactoryType::SINK,
+            "src" | "source" => gst::ElementFactoryType::SRC,
+            "muxer" => gst::ElementFactoryType::MUXER,
+            "demuxer" => gst::ElementFactoryType::DEMUXER,
+            "parser" => gst::ElementFactoryType::PARSER,
+            "payloader" => gst::ElementFactoryType::PAYLOADER,
+            "depayloader" => gst::ElementFactoryType::DEPAYLOADER,
+            "formatter" => gst::ElementFactoryType::FORMATTER,
+            "media-video" => gst::ElementFactoryType::MEDIA_VIDEO,
+            "media-audio" => gst::ElementFactoryType::MEDIA_AUDIO,
+            "media-image" => gst::ElementFactoryType::MEDIA_IMAGE,
+            "media-subtitle" => gst::ElementFactoryType::MEDIA_SUBTITLE,
+            "media-metadata" => gst::ElementFactoryType::MEDIA_METADATA,
+            _ => gst::ElementFactoryType::empty(),
+        })
+        .fold(gst::ElementFactoryType::empty(), |x, y| x | y);
+
+    Some(kind).filter(|x| !x.is_empty())
+}
+
+/// What completion needs of an element factory, kept in the cache between
+/// runs.
+#[derive(Clone, Debug, PartialEq)]
+struct Factory {
+    name: String,
+    rank: i32,
+    klass: String,
+    templates: Vec<Template>,
+}
+
+/// A pad template, `name` being the name template.
+#[derive(Clone, Debug, PartialEq)]
+struct Template {
+    name: String,
+    direction: PadDirection,
+    caps: Caps,
+}
+
+impl Factory {
+    fn new(factory: &ElementFactory) -> Self {
+        Factory {
+            name: factory.name().to_string(),
+            rank: factory.rank().into_glib(),
+            klass: factory
+                .metadata(gst::ELEMENT_METADATA_KLASS)
+                .unwrap_or_default()
+                .to_owned(),
+            templates: factory
+                .static_pad_templates()
+                .iter()
+                .map(|x| Template {
+                    name: x.name_template().to_string(),
+                    direction: x.direction(),
+                    caps: x.caps(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The factory `name` from the list, or the registry if it isn't listed.
+    fn find(name: &str) -> Option<Factory> {
+        LIST.iter()
+            .find(|x| x.name == name)
+            .cloned()
+            .or_else(|| gst::ElementFactory::find(name).map(|x| Factory::new(&x)))
+    }
+
+    /// Caps of the pad templates in `direction`, only those `pad` could be
+    /// made from if given.
+    fn pad_caps(&self, direction: PadDirection, pad: Option<&str>) -> Caps {
+        self.templates
+            .iter()
+            .filter(|x| {
+                if x.direction == direction {
+                    if let Some(p) = pad {
+                        p.starts_with(x.name.split('%').next().unwrap())
+                    } else {
+                        true
+                    }
                 } else {
-                    //println!("couldnt find {:?}", elem);
+                    false
+                }
+            })
+            .fold(gst::Caps::new_empty(), |mut x, y| {
+                x.merge(y.caps.clone());
+                x
+            })
+    }
+
+    fn can_sink_any_caps(&self, caps: &Caps) -> bool {
+        self.templates
+            .iter()
+            .any(|x| x.direction == PadDirection::Sink && x.caps.can_intersect(caps))
+    }
+
+    fn can_sink_all_caps(&self, caps: &Caps) -> bool {
+        self.templates
+            .iter()
+            .any(|x| x.direction == PadDirection::Sink && caps.is_subset(&x.caps))
+    }
+
+    /// The factory as cache lines, one for itself and a tab-indented one
+    /// for each pad template.
+    fn to_cache(&self) -> String {
+        let mut lines = format!("{}\t{}\t{}\n", self.name, self.rank, self.klass);
+
+        for template in self.templates.iter() {
+            let direction = match template.direction {
+                PadDirection::Src => "src",
+                PadDirection::Sink => "sink",
+                _ => "unknown",
+            };
+
+            lines.push_str(&format!(
+                "\t{}\t{}\t{}\n",
+                direction, template.name, template.caps
+            ));
+        }
+
+        lines
+    }
+
+    /// Reads back factories written by `to_cache`, none at all if anything
+    /// is off.
+    fn parse_list(s: &str) -> Option<Vec<Factory>> {
+        let mut list: Vec<Factory> = vec![];
+
+        for line in s.lines() {
+            match line.strip_prefix('\t') {
+                Some(template) => {
+                    let mut fields = template.splitn(3, '\t');
+                    let direction = match fields.next()? {
+                        "src" => PadDirection::Src,
+                        "sink" => PadDirection::Sink,
+                        _ => PadDirection::Unknown,
+                    };
+                    let name = fields.next()?.to_owned();
+                    let caps = fields.next()?.parse().ok()?;
+
+                    list.last_mut()?.templates.push(Template {
+                        name,
+                        direction,
+                        caps,
+                    });
+                }
+                None => {
+                    let mut fields = line.splitn(3, '\t');
+
+                    list.push(Factory {
+                        name: fields.next()?.to_owned(),
+                        rank: fields.next()?.parse().ok()?,
+                        klass: fields.next()?.to_owned(),
+                        templates: vec![],
+                    });
                 }
             }
         }
 
-        /*for x in v.iter() {
-            println!("{:?}", x.longname());
-        }*/
+        if list.is_empty() {
+            None
+        } else {
+            Some(list)
+        }
+    }
+}
 
-        v
-    };
+/// Whether an element of `klass` usually follows one of `upstream`, going by
+/// the parts of the klasses like `Codec/Parser/Video`.
+fn follows(upstream: &str, klass: &str) -> bool {
+    let has = |klass: &str, part: &str| klass.split('/').any(|x| x == part);
+
+    FOLLOWS
+        .iter()
+        .any(|(a, b)| has(upstream, a) && has(klass, b))
+}
+
+/// The factories in the list by the media types their sink templates take,
+/// like `video/x-raw`, so that finding those that can take some caps is a
+/// lookup and a few intersections rather than a pass over all of them.
+struct CapsIndex {
+    /// Sink caps of each factory, by position in the list.
+    sink_caps: Vec<Caps>,
+    media_types: HashMap<String, Vec<usize>>,
+    /// Factories with a template taking ANY caps, which match every type.
+    any: Vec<usize>,
+}
+
+impl CapsIndex {
+    fn new(list: &[Factory]) -> Self {
+        let sink_caps = list
+            .iter()
+            .map(|x| x.pad_caps(PadDirection::Sink, None))
+            .collect::<Vec<_>>();
+
+        let mut media_types: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut any = vec![];
+
+        for (i, caps) in sink_caps.iter().enumerate() {
+            if caps.is_any() {
+                any.push(i);
+                continue;
+            }
+
+            for structure in caps.iter() {
+                let factories = media_types.entry(structure.name().to_string()).or_default();
+
+                if factories.last() != Some(&i) {
+                    factories.push(i);
+                }
+            }
+        }
+
+        CapsIndex {
+            sink_caps,
+            media_types,
+            any,
+        }
+    }
+
+    /// Positions of the factories that can take `caps`, in list order.
+    fn sinking(&self, caps: &Caps) -> Vec<usize> {
+        let mut found = if caps.is_any() {
+            (0..self.sink_caps.len()).collect()
+        } else {
+            let mut found = self.any.clone();
+
+            for structure in caps.iter() {
+                if let Some(factories) = self.media_types.get(structure.name().as_str()) {
+                    found.extend(factories);
+                }
+            }
+
+            found.sort_unstable();
+            found.dedup();
+            found
+        };
+
+        found.retain(|&i| self.sink_caps[i].can_intersect(caps));
+        found
+    }
 }
 
 pub struct BashGstElement {
-    element: Element,
+    factory: ElementFactory,
+    properties: Vec<PropertyInfo>,
     caps: Caps,
 }
 
 impl BashGstElement {
+    /// Properties of the element as completion sees them.
+    pub fn get_properties(&self) -> Vec<PropertyInfo> {
+        self.properties.clone()
+    }
+
+    /// Looks up `name`, or `child::name` on a child of the element.
+    pub fn get_property(&self, name: &str) -> Option<PropertyInfo> {
+        match name.rsplit_once("::") {
+            Some((path, name)) => self
+                .get_child_properties(path)
+                .into_iter()
+                .find(|x| x.name == name),
+            None => self.properties.iter().find(|x| x.name == name).cloned(),
+        }
+    }
+
+    /// Names of the settable properties not in `filter`. Past a `::` the
+    /// names are those of the children and their properties.
     pub fn get_property_names(&self, filter: &[&str], prefix: Option<&str>) -> Vec<String> {
-        self.element
-            .list_properties()
+        let prefix = prefix.unwrap_or("");
+
+        let (path, properties) = match prefix.rsplit_once("::") {
+            Some((path, _)) => (path, self.get_child_properties(path)),
+            None => ("", self.get_properties()),
+        };
+
+        let head = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{}::", path)
+        };
+
+        // Only the name after the last `::` is matched.
+        let tail = &prefix[head.len()..];
+
+        let settable = properties.into_iter().filter(|x| x.is_settable());
+        let properties = fuzzy::filter(Some(tail), settable, |x| x.name.as_str())
             .into_iter()
-            .filter_map(|x| {
-                let name = x.name();
+            .map(|x| format!("{}{}", head, x.name))
+            .filter(|name| !filter.contains(&name.as_str()))
+            .collect::<Vec<_>>();
 
-                match if let Some(p) = prefix {
-                    if name.starts_with(p) {
-                        &name
-                    } else {
-                        ""
-                    }
-                } else {
-                    &name
-                } {
-                    "" => None,
-                    x if !filter.contains(&x) => Some(x.to_owned()),
+        let children = if path.is_empty() && !self.is_child_proxy() {
+            vec![]
+        } else {
+            let names = self
+                .get_child_names(path)
+                .into_iter()
+                .map(|x| format!("{}::", x));
+
+            fuzzy::filter(Some(tail), names, |x| x.as_str())
+                .into_iter()
+                .map(|x| format!("{}{}", head, x))
+                .collect()
+        };
+
+        properties.into_iter().chain(children).collect()
+    }
+
+    fn is_child_proxy(&self) -> bool {
+        self.factory
+            .load()
+            .ok()
+            .is_some_and(|x| x.element_type().is_a(gst::ChildProxy::static_type()))
+    }
+
+    /// The child at `path`, `::`-separated names through nested child proxies,
+    /// on a newly created element. Sink pads are requested as needed, which
+    /// is how mixers expose them as children.
+    fn get_child(&self, path: &str) -> Option<glib::Object> {
+        let element = self.factory.create().build().ok()?;
+        let mut child = element.clone().upcast::<glib::Object>();
+
+        for (i, name) in path.split("::").filter(|x| !x.is_empty()).enumerate() {
+            let next = child
+                .dynamic_cast_ref::<gst::ChildProxy>()?
+                .child_by_name(name)
+                .or_else(|| match i {
+                    0 => element.request_pad_simple(name).map(|x| x.upcast()),
                     _ => None,
-                }
+                })?;
+
+            child = next;
+        }
+
+        Some(child)
+    }
+
+    fn get_child_properties(&self, path: &str) -> Vec<PropertyInfo> {
+        self.get_child(path)
+            .map(|x| x.list_properties().iter().map(PropertyInfo::new).collect())
+            .unwrap_or_default()
+    }
+
+    /// Names of the children at `path`, along with the sink pads that would
+    /// be requested on the element itself.
+    fn get_child_names(&self, path: &str) -> Vec<String> {
+        let mut names = self
+            .get_child(path)
+            .and_then(|x| x.dynamic_cast::<gst::ChildProxy>().ok())
+            .map(|proxy| {
+                (0..proxy.children_count())
+                    .filter_map(|i| proxy.child_by_index(i))
+                    .filter_map(|x| x.downcast::<gst::Object>().ok())
+                    .map(|x| x.name().to_string())
+                    .collect::<Vec<_>>()
             })
-            .collect()
+            .unwrap_or_default();
+
+        if path.is_empty() {
+            for pad in get_pad_names(&self.factory.name(), PadDirection::Sink, &[]) {
+                if !names.contains(&pad) {
+                    names.push(pad);
+                }
+            }
+        }
+
+        names
     }
 
-    pub fn get_compatible_elements(&self, prefix: Option<&str>) -> Vec<String> {
-        let mut compat = LIST
+    pub fn get_uri_protocols(&self) -> Vec<String> {
+        self.factory
+            .uri_protocols()
             .iter()
-            .filter(|factory| {
-                if let Some(p) = prefix {
-                    if factory.name().starts_with(p) == false {
-                        return false;
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    /// Completes a partially typed caps string from the src caps, one level
+    /// at a time: media type, then field name, then field value.
+    pub fn get_caps_completions(&self, partial: &str) -> Vec<String> {
+        let mut completions: Vec<String> = vec![];
+
+        let (head, tail) = match partial.rfind(',') {
+            Some(i) => partial.split_at(i + 1),
+            None => {
+                for structure in self.caps.iter() {
+                    let name = structure.name().to_string();
+
+                    if name.starts_with(partial) && !completions.contains(&name) {
+                        completions.push(name);
                     }
                 }
-                factory.can_sink_any_caps(&self.caps)
+
+                return completions;
+            }
+        };
+
+        let media_type = head
+            .split(|c: char| c == ',' || c == '(')
+            .next()
+            .unwrap()
+            .trim();
+        let lead = &tail[..tail.len() - tail.trim_start().len()];
+        let tail = tail.trim_start();
+
+        for structure in self.caps.iter().filter(|x| x.has_name(media_type)) {
+            let candidates = match tail.split_once('=') {
+                None => structure
+                    .fields()
+                    .map(|field| field.to_string())
+                    .filter(|field| {
+                        field.starts_with(tail) && !head.contains(&format!("{}=", field))
+                    })
+                    .collect(),
+                Some((field, prefix)) => match structure.value(field.trim_end()) {
+                    Ok(value) => get_caps_values(value)
+                        .into_iter()
+                        .filter(|value| value.starts_with(prefix.trim_start()))
+                        .map(|value| format!("{}={}", field, value))
+                        .collect(),
+                    Err(_) => vec![],
+                },
+            };
+
+            for candidate in candidates {
+                let completion = format!("{}{}{}", head, lead, candidate);
+
+                if !completions.contains(&completion) {
+                    completions.push(completion);
+                }
+            }
+        }
+
+        completions
+    }
+
+    /// Sink pad names of the factory `name` that can take the src caps.
+    pub fn get_sink_pad_names(&self, name: &str, used: &[&str]) -> Vec<String> {
+        get_pad_names_matching(name, used, |x| {
+            x.direction == PadDirection::Sink && x.caps.can_intersect(&self.caps)
+        })
+    }
+
+    pub fn can_link_to(&self, name: &str) -> bool {
+        !self.get_sink_pad_names(name, &[]).is_empty()
+    }
+
+    /// Narrows the src caps to what an inline caps filter lets through.
+    pub fn intersect_caps(&mut self, filter: &str) {
+        if let Ok(filter) = filter.parse::<Caps>() {
+            self.caps = self.caps.intersect(&filter);
+        }
+    }
+
+    /// How fitting `factory` is to follow the element, its sink caps being
+    /// `sink_caps` and the link having been used `uses` times before. Its
+    /// rank adds up with whether it takes all of the src caps, how little
+    /// else it takes, whether its klass usually comes next and the uses.
+    /// Taking ANY caps counts against it.
+    fn relevance(&self, factory: &Factory, sink_caps: &Caps, uses: u32) -> i64 {
+        let mut score = i64::from(factory.rank.clamp(0, 2 * RANK_WEIGHT));
+
+        if factory.can_sink_all_caps(&self.caps) {
+            score += RANK_WEIGHT;
+        }
+
+        if sink_caps.is_any() {
+            score -= 2 * RANK_WEIGHT;
+        } else if !sink_caps.is_empty() {
+            let matching = sink_caps
+                .iter()
+                .filter(|x| self.caps.iter().any(|y| y.can_intersect(x)))
+                .count();
+
+            score += RANK_WEIGHT * matching as i64 / sink_caps.size() as i64;
+        }
+
+        let klass = self
+            .factory
+            .metadata(gst::ELEMENT_METADATA_KLASS)
+            .unwrap_or_default();
+
+        if follows(klass, &factory.klass) {
+            score += RANK_WEIGHT;
+        }
+
+        score + RANK_WEIGHT / 4 * i64::from(uses.min(MAX_USES))
+    }
+
+    /// Elements that can take the src caps, and feed the factory and pad of
+    /// `downstream` if there is one, the most relevant first.
+    pub fn get_compatible_elements(
+        &self,
+        prefix: Option<&str>,
+        downstream: Option<(&str, Option<&str>)>,
+    ) -> Vec<String> {
+        let sink_caps = downstream.and_then(|(name, pad)| {
+            Factory::find(name).map(|factory| factory.pad_caps(PadDirection::Sink, pad))
+        });
+
+        let upstream = self.factory.name().to_string();
+        let history = history::load();
+
+        let matching = fuzzy::filter(prefix, INDEX.sinking(&self.caps), |&i| {
+            LIST[i].name.as_str()
+        });
+
+        let mut compat = matching
+            .into_iter()
+            .filter(|&i| {
+                sink_caps.as_ref().is_none_or(|sink_caps| {
+                    LIST[i]
+                        .pad_caps(PadDirection::Src, None)
+                        .can_intersect(sink_caps)
+                })
+            })
+            .map(|i| {
+                let uses = history
+                    .get(&(upstream.clone(), LIST[i].name.clone()))
+                    .copied()
+                    .unwrap_or(0);
+
+                (i, self.relevance(&LIST[i], &INDEX.sink_caps[i], uses))
             })
             .collect::<Vec<_>>();
 
-        compat.sort_by(|a, b| {
-            let anycaps = gst::Caps::new_any();
+        // The list is by name, so ties go in order of it.
+        compat.sort_by_key(|&(i, score)| (std::cmp::Reverse(score), i));
 
-            if a.can_sink_all_caps(&anycaps) {
-                return std::cmp::Ordering::Greater;
-            } else if b.can_sink_all_caps(&anycaps) {
-                return std::cmp::Ordering::Less;
+        // How well the name matches comes before all that.
+        fuzzy::filter(prefix, compat, |&(i, _)| LIST[i].name.as_str())
+            .into_iter()
+            .map(|(i, _)| LIST[i].name.clone())
+            .collect()
+    }
+
+    /// `converter ! element` chains for the elements starting with `prefix`
+    /// that can't take the src caps directly. The shortest chain is found
+    /// for each, going through the factories classed as converters.
+    pub fn get_converter_chains(&self, prefix: &str) -> Vec<String> {
+        let mut targets = LIST
+            .iter()
+            .filter(|x| x.name.starts_with(prefix) && !x.can_sink_any_caps(&self.caps))
+            .collect::<Vec<_>>();
+
+        let converters = LIST
+            .iter()
+            .filter(|x| x.klass.contains("Converter"))
+            .collect::<Vec<_>>();
+
+        let mut chains = vec![];
+        let mut frontier = vec![(vec![], self.caps.clone())];
+
+        for _ in 0..CHAIN_HOPS {
+            if targets.is_empty() {
+                break;
             }
 
-            let acaps = a
-                .static_pad_templates()
-                .iter()
-                .filter(|x| x.direction() == gst::PadDirection::Sink)
-                .fold(gst::Caps::new_empty(), |mut x, y| {
-                    x.merge(y.caps());
-                    x
-                });
+            let mut next = vec![];
 
-            let bcaps = b
-                .static_pad_templates()
-                .iter()
-                .filter(|x| x.direction() == gst::PadDirection::Sink)
-                .fold(gst::Caps::new_empty(), |mut x, y| {
-                    x.merge(y.caps());
-                    x
-                });
+            for (chain, caps) in frontier {
+                for converter in converters.iter() {
+                    if chain.contains(converter) || !converter.can_sink_any_caps(&caps) {
+                        continue;
+                    }
 
-            if acaps.is_subset(&bcaps) {
-                std::cmp::Ordering::Less
-            } else {
-                std::cmp::Ordering::Greater
+                    let mut chain = chain.clone();
+                    chain.push(*converter);
+                    next.push((chain, converter.pad_caps(PadDirection::Src, None)));
+                }
             }
-        });
 
-        compat.into_iter().map(|x| x.name().to_string()).collect()
+            for (chain, caps) in next.iter() {
+                targets.retain(|target| {
+                    if !target.can_sink_any_caps(caps) {
+                        return true;
+                    }
+
+                    let names = chain
+                        .iter()
+                        .chain(std::iter::once(target))
+                        .map(|x| x.name.as_str())
+                        .collect::<Vec<_>>();
+
+                    chains.push(names.join(" ! "));
+                    false
+                });
+            }
+
+            frontier = next;
+        }
+
+        chains
     }
 }
 
-fn get_src_caps(factory: &ElementFactory, pad: Option<&str>) -> Caps {
+/// Concrete values of a caps field, ranges can't be enumerated.
+fn get_caps_values(value: &gst::glib::Value) -> Vec<String> {
+    if let Ok(list) = value.get::<gst::List>() {
+        list.iter().flat_map(|x| get_caps_values(x)).collect()
+    } else {
+        value
+            .serialize()
+            .ok()
+            .map(|x| x.to_string())
+            .filter(|x| !x.starts_with('['))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Pad names of the factory `name` in `direction`, templated names filled in
+/// with the first index not in `used`.
+pub fn get_pad_names(name: &str, direction: PadDirection, used: &[&str]) -> Vec<String> {
+    get_pad_names_matching(name, used, |x| x.direction == direction)
+}
+
+fn get_pad_names_matching<F>(name: &str, used: &[&str], filter: F) -> Vec<String>
+where
+    F: Fn(&Template) -> bool,
+{
+    let factory = match Factory::find(name) {
+        Some(factory) => factory,
+        None => return vec![],
+    };
+
     factory
-        .static_pad_templates()
-        .into_iter()
-        .filter(|x| {
-            if x.direction() == gst::PadDirection::Src {
-                if let Some(p) = pad {
-                    p.starts_with(x.name_template().split('%').next().unwrap())
-                } else {
-                    true
-                }
-            } else {
-                false
+        .templates
+        .iter()
+        .filter(|x| filter(x))
+        .filter_map(|x| {
+            let template = x.name.clone();
+
+            if !template.contains('%') {
+                return Some(template);
             }
+
+            (0..)
+                .map(|i| {
+                    template
+                        .replace("%u", &i.to_string())
+                        .replace("%d", &i.to_string())
+                })
+                .find(|pad| !used.contains(&pad.as_str()))
+                .filter(|pad| !pad.contains('%'))
         })
-        .fold(gst::Caps::new_empty(), |mut x, y| {
-            x.merge(y.caps());
+        .collect()
+}
+
+pub fn get_elements(prefix: Option<&str>) -> Vec<String> {
+    fuzzy::filter(prefix, LIST.iter(), |x| x.name.as_str())
+        .into_iter()
+        .map(|x| x.name.clone())
+        .collect()
+}
+
+/// Caps of a file going by its extension, as the typefinders know them.
+fn get_file_caps(path: &str) -> Caps {
+    let extension = match path.rsplit_once('.') {
+        Some((_, extension)) if !extension.contains('/') => extension.to_lowercase(),
+        _ => return Caps::new_empty(),
+    };
+
+    gst::TypeFindFactory::factories()
+        .into_iter()
+        .filter(|x| x.extensions().iter().any(|x| x.as_str() == extension))
+        .filter_map(|x| x.caps())
+        .fold(Caps::new_empty(), |mut x, y| {
+            x.merge(y);
             x
         })
 }
 
-pub fn get_elements(prefix: Option<&str>) -> Vec<String> {
-    LIST.iter()
-        .filter_map(|x| {
-            let name = x.name().to_string();
+/// Whether `sink` can end a chain producing `caps`. What a demuxer produces
+/// is unknown until an element after it says, and a sink taking ANY caps
+/// only ends a chain producing raw media of the kind in its klass, like
+/// `Sink/Video`.
+fn can_end(sink: &Factory, sink_caps: &Caps, caps: &Caps) -> bool {
+    if caps.is_any() || caps.is_empty() {
+        return false;
+    }
 
-            if let Some(p) = prefix {
-                if name.starts_with(p) {
-                    Some(name)
-                } else {
-                    None
+    if !sink_caps.is_any() {
+        return sink_caps.can_intersect(caps);
+    }
+
+    let media = sink
+        .klass
+        .split('/')
+        .map(str::to_lowercase)
+        .find(|x| x == "video" || x == "audio");
+
+    caps.iter().all(|x| {
+        let (kind, subtype) = x.name().split_once('/').unwrap_or_default();
+        subtype == "x-raw" && media.as_ref().is_none_or(|media| kind == media)
+    })
+}
+
+/// Full pipelines from `source`, an element or else a file, to the element
+/// `sink`. The search goes through the elements autoplugging would pick and
+/// the converters, a few hops at most, each used once per pipeline, and
+/// prefers the shortest pipelines and then the highest ranked elements.
+pub fn plan(source: &str, sink: &str) -> Vec<String> {
+    let sink = match Factory::find(sink) {
+        Some(sink) => sink,
+        None => return vec![],
+    };
+
+    let (head, caps) = match Factory::find(source) {
+        Some(factory) => (source.to_owned(), factory.pad_caps(PadDirection::Src, None)),
+        None if source.contains(char::is_whitespace) => (
+            format!("filesrc location=\"{}\"", source),
+            get_file_caps(source),
+        ),
+        None => (
+            format!("filesrc location={}", source),
+            get_file_caps(source),
+        ),
+    };
+
+    let sink_caps = sink.pad_caps(PadDirection::Sink, None);
+
+    // Src caps by position in the list, for the elements that may be hops.
+    // Those taking anything, like queues, lead nowhere. Those producing
+    // anything, like demuxers, are narrowed down by the element after them.
+    let candidates = LIST
+        .iter()
+        .zip(INDEX.sink_caps.iter())
+        .map(|(factory, sink_caps)| {
+            let src_caps = factory.pad_caps(PadDirection::Src, None);
+
+            Some(src_caps).filter(|src_caps| {
+                (factory.rank > gst::Rank::NONE.into_glib() || factory.klass.contains("Converter"))
+                    && !sink_caps.is_empty()
+                    && !sink_caps.is_any()
+                    && !src_caps.is_empty()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut found = vec![];
+    let mut frontier = vec![(vec![], caps)];
+
+    for _ in 0..=PLAN_HOPS {
+        for (chain, caps) in frontier.iter() {
+            if can_end(&sink, &sink_caps, caps) {
+                found.push(chain.clone());
+            }
+        }
+
+        if found.len() >= PLAN_RESULTS {
+            break;
+        }
+
+        let mut next = vec![];
+
+        for (chain, caps) in frontier.iter() {
+            let last = chain.last();
+
+            for i in INDEX.sinking(caps) {
+                let factory = &LIST[i];
+
+                let src_caps = match &candidates[i] {
+                    Some(src_caps) if !chain.contains(&factory) => src_caps,
+                    _ => continue,
+                };
+
+                // Any element would take what's unknown, so only those that
+                // usually come next do, like a parser after a demuxer.
+                if caps.is_any() && !last.is_some_and(|x| follows(&x.klass, &factory.klass)) {
+                    continue;
                 }
-            } else {
-                Some(name)
+
+                let mut chain = chain.clone();
+                chain.push(factory);
+                next.push((chain, src_caps.clone()));
             }
+        }
+
+        next.sort_by_key(|(chain, _)| -chain.iter().map(|x| x.rank).sum::<i32>());
+        next.truncate(PLAN_FRONTIER);
+        frontier = next;
+    }
+
+    found.sort_by_key(|chain: &Vec<&Factory>| {
+        let rank = chain.iter().map(|x| x.rank).sum::<i32>();
+        (chain.len(), -rank)
+    });
+
+    found
+        .into_iter()
+        .take(PLAN_RESULTS)
+        .map(|chain| {
+            std::iter::once(head.clone())
+                .chain(chain.iter().map(|x| x.name.clone()))
+                .chain(std::iter::once(sink.name.clone()))
+                .collect::<Vec<_>>()
+                .join(" ! ")
         })
         .collect()
 }
 
+/// Properties installed on the element's class, read without creating an
+/// instance. Loading the plugin is enough for the type to be registered.
+fn get_class_properties(factory: &ElementFactory) -> Option<Vec<glib::ParamSpec>> {
+    let factory = factory.load().ok()?;
+    let class = glib::object::ObjectClass::from_type(factory.element_type())?;
+
+    Some(class.list_properties().to_vec())
+}
+
+/// Properties of the factory's element, cached once read off the class. The
+/// element is only created when its class can't be looked up, and goes
+/// without properties if that fails too, e.g. for a missing device.
+fn get_properties(factory: &ElementFactory) -> Vec<PropertyInfo> {
+    let path = format!("properties/{}", factory.name());
+
+    if let Some(properties) = cache::read(&path).and_then(|x| {
+        x.lines()
+            .map(PropertyInfo::from_cache)
+            .collect::<Option<Vec<_>>>()
+    }) {
+        return properties;
+    }
+
+    match get_class_properties(factory) {
+        Some(properties) => {
+            let properties = properties.iter().map(PropertyInfo::new).collect::<Vec<_>>();
+            let contents = properties
+                .iter()
+                .map(|x| format!("{}\n", x.to_cache()))
+                .collect::<String>();
+
+            cache::write(&path, &contents);
+            properties
+        }
+        None => factory
+            .create()
+            .build()
+            .map(|x| x.list_properties().iter().map(PropertyInfo::new).collect())
+            .unwrap_or_default(),
+    }
+}
+
 pub fn find_element(name: &str, pad: Option<&str>) -> Option<BashGstElement> {
-    if let Some(factory) = gst::ElementFactory::find(name) {
-        let caps = get_src_caps(&factory, pad);
+    let factory = gst::ElementFactory::find(name)?;
+    let caps = Factory::find(name)?.pad_caps(PadDirection::Src, pad);
+    let properties = get_properties(&factory);
 
-        Some(BashGstElement {
-            element: factory.create().build().unwrap(),
-            caps,
-        })
-    } else {
-        None
+    Some(BashGstElement {
+        factory,
+        properties,
+        caps,
+    })
+}
+
+/// Lists the factories and indexes their caps up front, rather than on the
+/// first completion.
+pub fn warm_up() {
+    lazy_static::initialize(&INDEX);
+}
+
+/// Directories plugins were loaded from, along with those on the plugin path
+/// that may have none yet.
+pub fn plugin_dirs() -> Vec<PathBuf> {
+    let mut dirs = gst::Registry::get()
+        .plugins()
+        .iter()
+        .filter_map(|x| x.filename()?.parent().map(Path::to_path_buf))
+        .collect::<Vec<_>>();
+
+    for var in ["GST_PLUGIN_PATH", "GST_PLUGIN_PATH_1_0"] {
+        if let Some(paths) = std::env::var_os(var) {
+            dirs.extend(std::env::split_paths(&paths));
+        }
     }
+
+    dirs.sort();
+    dirs.dedup();
+    dirs
 }
 
-pub fn init() {
-    gst::init().unwrap();
+/// Whether GStreamer could be initialized, there's nothing to complete
+/// otherwise.
+pub fn init() -> bool {
+    gst::init().is_ok()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::property::ValueKind;
 
     #[test]
     fn test2() {
@@ -228,27 +1045,285 @@ mod tests {
         }
     }
 
-    /*#[test]
-    fn test5() {
+    #[test]
+    fn test6() {
+        gst::init().unwrap();
+        let mut element = find_element("videotestsrc", None).unwrap();
+
+        element.intersect_caps("audio/x-raw");
+        assert!(element.get_compatible_elements(None, None).is_empty());
+    }
+
+    #[test]
+    fn test7() {
+        gst::init().unwrap();
+        let mut element = find_element("videotestsrc", None).unwrap();
+
+        element.intersect_caps("video/x-raw");
+        assert_eq!(element.get_caps_completions("vid"), vec!["video/x-raw"]);
+        assert!(element
+            .get_caps_completions("video/x-raw,for")
+            .contains(&"video/x-raw,format".to_string()));
+        assert!(element
+            .get_caps_completions("video/x-raw, format=NV")
+            .contains(&"video/x-raw, format=NV12".to_string()));
+    }
+
+    #[test]
+    fn test8() {
+        gst::init().unwrap();
+
+        assert!(get_pad_names("qtdemux", PadDirection::Src, &[]).contains(&"video_0".to_string()));
+
+        let pads = get_pad_names("mp4mux", PadDirection::Sink, &["audio_0"]);
+        assert!(pads.contains(&"audio_1".to_string()));
+        assert!(pads.contains(&"video_0".to_string()));
+    }
+
+    #[test]
+    fn test9() {
+        gst::init().unwrap();
+        let element = find_element("voaacenc", None).unwrap();
+
+        assert!(element.can_link_to("mp4mux"));
+        assert!(!element.can_link_to("fakevideosink"));
+
+        let pads = element.get_sink_pad_names("mp4mux", &[]);
+        assert!(pads.contains(&"audio_0".to_string()));
+        assert!(!pads.contains(&"video_0".to_string()));
+    }
+
+    #[test]
+    fn test10() {
+        gst::init().unwrap();
+        let element = find_element("videotestsrc", None).unwrap();
+
+        let pattern = element.get_property("pattern").unwrap().kind;
+        assert_eq!(pattern.complete("")[0], "smpte");
+        assert!(pattern.complete("").contains(&"ball".to_string()));
+        assert_eq!(
+            element.get_property("is-live").unwrap().kind,
+            ValueKind::Boolean(false)
+        );
+
+        match element.get_property("num-buffers").unwrap().kind {
+            ValueKind::Number(range) => {
+                assert_eq!(range.to_string(), "[-1..2147483647]");
+                assert_eq!(range.default.to_string(), "-1");
+            }
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test11() {
         gst::init().unwrap();
+
+        let element = find_element("filesrc", None).unwrap();
         assert_eq!(
-            String::from_utf8(Command::new("gst-inspect-1.0").output().unwrap().stdout)
-                .unwrap()
-                .split("\n")
-                .filter_map(|x| {
-                    if x.is_empty() {
-                        None
-                    } else {
-                        let mut tokens = x.split(":");
-                        if tokens.next().unwrap().contains(" ") {
-                            None
-                        } else {
-                            Some(tokens.next().unwrap())
-                        }
-                    }
-                })
-                .count(),
-            LIST.len()
+            element.get_property("location").unwrap().kind,
+            ValueKind::Path
+        );
+        assert!(element.get_property("foo").is_none());
+        assert_eq!(element.get_uri_protocols(), vec!["file"]);
+
+        let element = find_element("capsfilter", None).unwrap();
+        assert_eq!(element.get_property("caps").unwrap().kind, ValueKind::Caps);
+
+        let element = find_element("playbin", None).unwrap();
+        assert_eq!(
+            element.get_property("video-sink").unwrap().kind,
+            ValueKind::Element
+        );
+    }
+
+    #[test]
+    fn test12() {
+        gst::init().unwrap();
+
+        // Device sources list their properties without opening the device.
+        let element = find_element("v4l2src", None).unwrap();
+        assert!(element.get_property("device").is_some());
+        assert!(find_element("foo", None).is_none());
+    }
+
+    #[test]
+    fn test13() {
+        gst::init().unwrap();
+        let names = find_element("fakesink", None)
+            .unwrap()
+            .get_property_names(&["sync"], None);
+
+        assert!(names.contains(&"silent".to_string()));
+        assert!(!names.contains(&"sync".to_string()));
+        assert!(!names.contains(&"last-sample".to_string()));
+        assert!(!names.contains(&"stats".to_string()));
+        assert!(!names.contains(&"parent".to_string()));
+    }
+
+    #[test]
+    fn test14() {
+        gst::init().unwrap();
+        let element = find_element("compositor", None).unwrap();
+
+        assert!(element
+            .get_property_names(&[], Some("sink_"))
+            .contains(&"sink_0::".to_string()));
+        assert_eq!(
+            element.get_property_names(&["sink_0::ypos"], Some("sink_0::xp")),
+            vec!["sink_0::xpos"]
         );
-    }*/
+        assert!(element
+            .get_property_names(&[], Some("sink_0::"))
+            .contains(&"sink_0::alpha".to_string()));
+
+        match element.get_property("sink_0::alpha").unwrap().kind {
+            ValueKind::Number(range) => assert_eq!(range.to_string(), "[0..1]"),
+            kind => panic!("{:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test15() {
+        gst::init().unwrap();
+        let element = find_element("videotestsrc", None).unwrap();
+        let compat = element.get_compatible_elements(None, Some(("x264enc", None)));
+
+        assert!(compat.contains(&"videoconvert".to_string()));
+        assert!(!compat.contains(&"autovideosink".to_string()));
+
+        let compat = element.get_compatible_elements(None, Some(("mp4mux", Some("audio_0"))));
+        assert!(!compat.contains(&"x264enc".to_string()));
+    }
+
+    #[test]
+    fn test16() {
+        gst::init().unwrap();
+        let mut element = find_element("videotestsrc", None).unwrap();
+
+        element.intersect_caps("video/x-raw,format=RGB");
+        assert!(element
+            .get_converter_chains("x264en")
+            .iter()
+            .any(|x| x.ends_with(" ! x264enc")));
+        assert!(element.get_converter_chains("videoconvert").is_empty());
+    }
+
+    #[test]
+    fn test17() {
+        gst::init().unwrap();
+
+        assert_eq!(
+            plan("videotestsrc", "fakevideosink")[0],
+            "videotestsrc ! fakevideosink"
+        );
+
+        let plans = plan("x.mp4", "autovideosink");
+        assert!(plans
+            .iter()
+            .any(|x| x.starts_with("filesrc location=x.mp4 ! qtdemux ! ")));
+        assert!(!plans.contains(&"filesrc location=x.mp4 ! autovideosink".to_owned()));
+        assert!(plan("videotestsrc", "foo").is_empty());
+
+        let sink = Factory {
+            name: "autovideosink".into(),
+            rank: 0,
+            klass: "Sink/Video".into(),
+            templates: vec![],
+        };
+        let any = Caps::new_any();
+
+        assert!(can_end(&sink, &any, &"video/x-raw".parse().unwrap()));
+        assert!(!can_end(&sink, &any, &"audio/x-raw".parse().unwrap()));
+        assert!(!can_end(&sink, &any, &"video/x-h264".parse().unwrap()));
+        assert!(!can_end(&sink, &any, &any));
+    }
+
+    #[test]
+    fn test18() {
+        gst::init().unwrap();
+        let factory = Factory::new(&gst::ElementFactory::find("qtdemux").unwrap());
+
+        assert_eq!(factory.klass, "Codec/Demuxer");
+        assert_eq!(
+            Factory::parse_list(&factory.to_cache()),
+            Some(vec![factory])
+        );
+        assert_eq!(Factory::parse_list("qtdemux\tprimary\t"), None);
+    }
+
+    #[test]
+    fn test19() {
+        gst::init().unwrap();
+
+        assert_eq!(parse_rank("Secondary"), Some(128));
+        assert_eq!(parse_rank("100"), Some(100));
+        assert_eq!(parse_rank("high"), None);
+        assert_eq!(
+            parse_factory_type("decoder, media-video"),
+            Some(gst::ElementFactoryType::DECODER | gst::ElementFactoryType::MEDIA_VIDEO)
+        );
+        assert_eq!(parse_factory_type(""), None);
+
+        let all = list_factories(0, None);
+        assert!(all.iter().any(|x| x.name() == "queue"));
+        assert!(all.iter().any(|x| x.name() == "videoconvert"));
+
+        let decoders = list_factories(
+            gst::Rank::PRIMARY.into_glib(),
+            parse_factory_type("decoder"),
+        );
+        assert!(decoders.iter().any(|x| x.name() == "jpegdec"));
+        assert!(!decoders.iter().any(|x| x.name() == "videotestsrc"));
+    }
+
+    #[test]
+    fn test20() {
+        gst::init().unwrap();
+
+        for caps in [
+            "video/x-raw",
+            "audio/x-raw, rate=44100",
+            "image/jpeg; video/x-h264",
+        ] {
+            let caps = caps.parse::<Caps>().unwrap();
+            let all = (0..LIST.len())
+                .filter(|&i| LIST[i].can_sink_any_caps(&caps))
+                .collect::<Vec<_>>();
+
+            assert_eq!(INDEX.sinking(&caps), all);
+        }
+
+        let queue = LIST.iter().position(|x| x.name == "queue").unwrap();
+        assert!(INDEX.any.contains(&queue));
+        assert_eq!(
+            INDEX.sinking(&"application/x-nothing".parse().unwrap()),
+            INDEX.any
+        );
+    }
+
+    #[test]
+    fn test21() {
+        gst::init().unwrap();
+
+        assert!(follows("Codec/Demuxer", "Codec/Parser/Video"));
+        assert!(follows("Codec/Parser/Video", "Codec/Decoder/Video"));
+        assert!(!follows(
+            "Codec/Encoder/Video",
+            "Codec/Depayloader/Network/RTP"
+        ));
+
+        let element = find_element("videotestsrc", None).unwrap();
+        let score = |name: &str, uses| {
+            let i = LIST.iter().position(|x| x.name == name).unwrap();
+            element.relevance(&LIST[i], &INDEX.sink_caps[i], uses)
+        };
+
+        assert!(score("videoconvert", 0) > score("queue", 0));
+        assert!(score("queue", MAX_USES) > score("queue", 0));
+        assert_eq!(score("queue", MAX_USES), score("queue", MAX_USES + 1));
+
+        let compat = element.get_compatible_elements(Some("video"), None);
+        assert_eq!(compat, element.get_compatible_elements(Some("video"), None));
+    }
 }
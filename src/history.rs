@@ -0,0 +1,106 @@
+use crate::cache;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Links of factories used, by how many times.
+pub type Links = HashMap<(String, String), u32>;
+
+/// Size the history grows to before its older half is dropped.
+const MAX_SIZE: u64 = 1 << 20;
+
+/// Kept apart from the cache, which starts over with every registry change.
+fn path() -> Option<PathBuf> {
+    let base = match std::env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").ok()?).join(".local/state"),
+    };
+
+    Some(base.join("gst-completion").join("history"))
+}
+
+/// Counts `upstream\tdownstream\tkey` records, those recorded again under
+/// the same key once. Lines that are off are skipped.
+fn parse(s: &str) -> Links {
+    let mut seen = HashSet::new();
+    let mut links = Links::new();
+
+    for line in s.lines() {
+        let mut fields = line.split('\t');
+
+        if let (Some(upstream), Some(downstream), Some(_), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        {
+            if seen.insert(line) {
+                *links
+                    .entry((upstream.to_owned(), downstream.to_owned()))
+                    .or_default() += 1;
+            }
+        }
+    }
+
+    links
+}
+
+/// How many times each `upstream ! downstream` link was used.
+pub fn load() -> Links {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|x| parse(&x))
+        .unwrap_or_default()
+}
+
+/// Records a use of the `upstream ! downstream` link, `key` telling a use
+/// apart from the same one recorded again. Records are appended, so that
+/// shells recording at once don't lose each other's.
+pub fn record(upstream: &str, downstream: &str, key: &str) {
+    let path = match path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+
+    let record = format!("{}\t{}\t{:016x}\n", upstream, downstream, hasher.finish());
+
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = file.write_all(record.as_bytes());
+    }
+
+    if fs::metadata(&path).is_ok_and(|x| x.len() > MAX_SIZE) {
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let lines = contents.lines().collect::<Vec<_>>();
+            let kept = lines[lines.len() / 2..]
+                .iter()
+                .map(|x| format!("{}\n", x))
+                .collect::<String>();
+
+            cache::write_file(&path, &kept);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test0() {
+        let links = parse(
+            "qtdemux\th264parse\t01\nqtdemux\th264parse\t01\nqtdemux\th264parse\t02\n\
+             h264parse\tavdec_h264\t01\nbroken\n",
+        );
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[&("qtdemux".to_owned(), "h264parse".to_owned())], 2);
+        assert_eq!(links[&("h264parse".to_owned(), "avdec_h264".to_owned())], 1);
+    }
+}
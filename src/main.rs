@@ -1,455 +1,741 @@
+mod cache;
+mod daemon;
+mod fuzzy;
 mod gstreamer;
+mod history;
+mod parser;
+mod property;
 
-use nom::branch::alt;
-use nom::bytes::complete::{is_a, is_not, take};
+use nom::bytes::complete::is_not;
 use nom::character::complete::{char, space0, space1};
-use nom::combinator::{map_res, not, opt, peek, rest};
-use nom::multi::{many0, many1};
-use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
+use nom::combinator::rest;
+use nom::sequence::{preceded, separated_pair, terminated, tuple};
 use nom::{AsChar, IResult};
 
+use parser::{Node, Pipeline};
+use property::ValueKind;
 use shell_completion::{BashCompletionInput, CompletionInput, CompletionSet};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-#[allow(clippy::type_complexity)]
-fn parse(
-    s: &str,
-) -> (
-    i8,
-    IResult<
-        &str,
-        Vec<(
-            &str,
-            Vec<(&str, &str)>,
-            Option<(Option<&str>, Option<&str>)>,
-        )>,
-    >,
-) {
-    let mut index = -1;
-
-    let res = many1(tuple((
-        delimited(
-            map_res(
-                tuple((
-                    char('!'),
-                    space1,
-                    many0(tuple((char('-'), is_not(" \t"), space0))),
-                )),
-                |x| {
-                    index += 1;
-                    Ok::<_, nom::Err<&str>>(x)
-                },
-            ),
-            map_res(is_not(" \t"), |s: &str| {
-                if s.chars()
-                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-                {
-                    Ok(s)
-                } else {
-                    Err(nom::Err::<&str>::Error("error"))
-                }
-            }),
-            tuple((space0, many0(tuple((char('-'), is_not(" \t"), space0))))),
-        ),
-        terminated(
-            many0(separated_pair(
-                preceded(
-                    peek(not(char('!'))),
-                    map_res(is_not("= \t"), |s: &str| {
-                        if s.chars()
-                            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
-                        {
-                            Ok(s)
-                        } else {
-                            Err(nom::Err::<&str>::Error("error"))
-                        }
-                    }),
-                ),
-                tuple((space0, char('='), space0)),
-                alt((
-                    delimited(
-                        is_a("\"\'"),
-                        is_not("\"\'"),
-                        tuple((
-                            take(1u8),
-                            space1,
-                            many0(tuple((char('-'), is_not(" \t"), space0))),
-                        )),
-                    ),
-                    map_res(
-                        terminated(
-                            terminated(is_not(" \t"), space1),
-                            many0(tuple((char('-'), is_not(" \t"), space0))),
-                        ),
-                        |s: &str| {
-                            if s.contains('\'') || s.contains('\"') {
-                                Err(nom::Err::<&str>::Error("error"))
-                            } else {
-                                Ok(s)
-                            }
-                        },
-                    ),
-                )),
-            )),
-            many0(tuple((char('-'), is_not(" \t"), space0))),
-        ),
-        map_res(
-            terminated(
-                opt(preceded(
-                    peek(not(char('!'))),
-                    separated_pair(opt(is_not(" \t=.")), char('.'), opt(is_not(" \t"))),
-                )),
-                space0,
-            ),
-            |x| {
-                if let Some(elem_pad) = x {
-                    if elem_pad.0.is_none() && elem_pad.1.is_none() {
-                        Err(nom::Err::<&str>::Error("error"))
-                    } else {
-                        Ok(x)
-                    }
-                } else {
-                    Ok(x)
-                }
-            },
-        ),
-    )))(s);
-
-    (index, res)
-}
-
-fn can_complete_path(rem: &str) -> bool {
+/// Splits an unfinished `prop=value` remainder into its name and value.
+fn split_property(rem: &str) -> Option<(&str, &str)> {
     let res: IResult<&str, (&str, &str)> = separated_pair(
-        terminated(is_not("= \t\'\""), space0),
+        terminated(is_not("= \t\'\",/"), space0),
         tuple((char('='), space0)),
         rest,
     )(rem);
 
-    if let Ok((o, (_, _))) = res {
-        o.is_empty()
+    if let Ok(("", (name, value))) = res {
+        Some((name, value))
     } else {
-        false
+        None
     }
 }
 
-fn is_remainder_sane(input: &BashCompletionInput, rem: &str) -> bool {
+/// Entries of the directory `partial` points into that start with its last
+/// component, directories ending in a slash.
+fn complete_path(partial: &str) -> Vec<String> {
+    let (dir, file) = partial.split_at(partial.rfind('/').map_or(0, |i| i + 1));
+
+    let search = match dir.strip_prefix('~') {
+        Some(rest) => format!("{}{}", std::env::var("HOME").unwrap_or_default(), rest),
+        None if dir.is_empty() => ".".to_owned(),
+        None => dir.to_owned(),
+    };
+
+    let mut paths = match std::fs::read_dir(search) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+
+                if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                    return None;
+                }
+
+                let slash = if entry.path().is_dir() { "/" } else { "" };
+                Some(format!("{}{}{}", dir, name, slash))
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+
+    paths.sort();
+    paths
+}
+
+/// Where bash starts the word, after the last `COMP_WORDBREAKS` character of
+/// `current_word`.
+fn word_start(current_word: &str) -> usize {
+    let breaks = std::env::var("COMP_WORDBREAKS").unwrap_or_else(|_| "\"'><=;|&(:".to_owned());
+
+    current_word
+        .rfind(|c: char| breaks.contains(c))
+        .map_or(0, |i| i + 1)
+}
+
+/// Where an unclosed quote in `s` opens, if any.
+fn open_quote(s: &str) -> Option<usize> {
+    let mut open = None;
+
+    for (i, c) in s.char_indices() {
+        match open {
+            None if c == '"' || c == '\'' => open = Some((i, c)),
+            Some((_, quote)) if c == quote => open = None,
+            _ => {}
+        }
+    }
+
+    open.map(|(i, _)| i)
+}
+
+/// Bash only replaces the part of the current word after the last
+/// `COMP_WORDBREAKS` character, or everything after an unclosed quote, so
+/// completions of the whole `tail` of the line are cut down to that part.
+fn trim_to_word(tail: &str, current_word: Option<&str>, completions: Vec<String>) -> Vec<String> {
+    let current_word = current_word.unwrap_or("");
+    let cut = match open_quote(tail) {
+        Some(i) => i + 1,
+        None => tail.len().saturating_sub(current_word.len()) + word_start(current_word),
+    };
+
+    completions
+        .into_iter()
+        .filter_map(|x| x.get(cut..).map(str::to_owned))
+        .collect()
+}
+
+/// Splits a pipeline description typed as a value into the word being
+/// completed in it and what comes before the part of the word that's
+/// completed.
+fn split_nested(value: &str) -> (&str, Option<&str>) {
+    let word = value
+        .rsplit(char::is_whitespace)
+        .next()
+        .filter(|x| !x.is_empty());
+    let cut = word.map_or(value.len(), |x| value.len() - x.len() + word_start(x));
+
+    (&value[..cut], word)
+}
+
+fn is_remainder_sane(rem: &str) -> bool {
     if rem.is_empty() {
         true
     } else if let Ok::<_, nom::Err<nom::error::Error<&str>>>(("", _)) =
         preceded(char('!'), space1)(rem)
     {
         true
-    } else if can_complete_path(rem) {
-        input.complete_file().suggest();
-        false
     } else if rem.chars().next().unwrap().is_alpha() {
         rem[1..]
             .chars()
-            .all(|x| x.is_alphanumeric() || x == '-' || x == '_')
+            .all(|x| x.is_alphanumeric() || x == '-' || x == '_' || x == ':')
     } else {
         false
     }
 }
 
-fn main() {
-    gstreamer::init();
+/// What completion works from: the line up to the cursor, what follows it
+/// and the names declared anywhere on it.
+struct Context<'a> {
+    args: &'a str,
+    pipeline: Pipeline<'a>,
+    /// Properties after the cursor still belonging to the last element.
+    following: Vec<&'a str>,
+    /// The pipeline after the cursor.
+    after: Pipeline<'a>,
+    names: HashMap<&'a str, &'a str>,
+    current_word: Option<&'a str>,
+    suggestions: RefCell<Vec<String>>,
+}
 
-    let input = BashCompletionInput::from_env().expect("Missing expected environment variables");
+impl<'a> Context<'a> {
+    fn suggest(&self, values: Vec<String>) {
+        self.suggestions.borrow_mut().extend(values);
+    }
 
-    let current_word = if input.current_word().is_empty() {
-        None
-    } else {
-        Some(input.current_word())
-    };
+    /// The element feeding the link at `index`, its src caps narrowed by the
+    /// caps filters along the way.
+    fn upstream(&self, index: usize) -> Option<gstreamer::BashGstElement> {
+        let (name, pad) = self.pipeline.link_source(index, &self.names)?;
+        let mut element = gstreamer::find_element(name, pad)?;
+
+        for caps in self.pipeline.link_filters(index) {
+            element.intersect_caps(caps.text);
+        }
+
+        Some(element)
+    }
 
-    let args = {
-        let mut c = input.args();
-        c[0] = "!";
-        c
+    /// The factory and pad linked into after the cursor, which the element
+    /// being typed has to feed.
+    fn downstream(&self) -> Option<(&'a str, Option<&'a str>)> {
+        match self.after.nodes.get(..2)? {
+            [Node::Link(_), Node::Element(element)] => Some((element.name, None)),
+            [Node::Link(_), Node::PadRef(pad)] => {
+                Some((self.names.get(pad.element?).copied()?, pad.pad))
+            }
+            _ => None,
+        }
     }
-    .join(" ");
 
-    if let (i, Ok((rem, parsed))) = parse(&args) {
-        if !is_remainder_sane(&input, rem) {
+    /// Counts a use of the link into the element feeding the link at `index`,
+    /// which was just typed out. A line up to the element counts once a day,
+    /// however many times it's completed at. Only links the element would
+    /// have been suggested at count, not typos or links that can't be made.
+    fn record_link(&self, index: usize) {
+        let element = match index.checked_sub(1).map(|i| &self.pipeline.nodes[i]) {
+            Some(Node::Element(element)) => element,
+            _ => return,
+        };
+
+        let link = match self.pipeline.link_before(index - 1) {
+            Some(link) => link,
+            None => return,
+        };
+
+        let suggested = gstreamer::find_element(element.name, None).is_some()
+            && self.upstream(link).is_some_and(|upstream| {
+                upstream
+                    .get_compatible_elements(Some(element.name), None)
+                    .iter()
+                    .any(|x| x == element.name)
+            });
+
+        if !suggested {
             return;
         }
 
-        let len = parsed.len();
-        assert!(len > 0);
+        if let Some((upstream, _)) = self.pipeline.link_source(link, &self.names) {
+            let end = element.span.start + element.name.len();
+            let day = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs() / (24 * 60 * 60));
+            let key = format!("{}\t{}", day, self.args.get(..end).unwrap_or(self.args));
 
-        if len as i8 == i
-            || (parsed[i as usize].1.is_empty() && current_word == Some(parsed[i as usize].0))
-        {
-            if i == 0 {
-                return gstreamer::get_elements(current_word).suggest();
-            }
+            history::record(upstream, element.name, &key);
+        }
+    }
 
-            let index = (i - 1) as usize;
-            let prev = &parsed[index];
-
-            let (prev_elem, prev_pad) = if let Some(elem_pad) = prev.2 {
-                match elem_pad.0 {
-                    Some(elem_name) => {
-                        let found = parsed.iter().find(|elem| {
-                            elem.1
-                                .iter()
-                                .any(|prop| prop.0 == "name" && prop.1 == elem_name)
-                        });
-
-                        if let Some(elem) = found {
-                            (elem.0, elem_pad.1)
-                        } else {
-                            return;
-                        }
+    /// Suggests the elements that can follow the link at `link`, or any
+    /// element at the start of a chain.
+    fn suggest_elements(&self, link: Option<usize>) {
+        match link {
+            None => self.suggest(gstreamer::get_elements(self.current_word)),
+            Some(index) => {
+                self.record_link(index);
+
+                if let Some(element) = self.upstream(index) {
+                    let mut suggestions =
+                        element.get_compatible_elements(self.current_word, self.downstream());
+
+                    if let Some(word) = self.current_word {
+                        suggestions.extend(element.get_caps_completions(word));
+                        suggestions.extend(element.get_converter_chains(word));
                     }
-                    _ => (prev.0, elem_pad.1),
+
+                    // Named elements that can be linked into from the left.
+                    let mut named = self
+                        .names
+                        .iter()
+                        .map(|(name, factory)| (format!("{}.", name), *factory))
+                        .filter(|(name, factory)| {
+                            name.starts_with(self.current_word.unwrap_or(""))
+                                && element.can_link_to(factory)
+                        })
+                        .map(|(name, _)| name)
+                        .collect::<Vec<_>>();
+
+                    named.sort();
+                    suggestions.extend(named);
+
+                    self.suggest(suggestions);
                 }
-            } else {
-                (prev.0, None)
+            }
+        }
+    }
+
+    /// Completes a caps string being typed after the link at `link`, `tail`
+    /// being the rest of the line from `prefix` on.
+    fn suggest_caps(&self, link: Option<usize>, tail: &str, prefix: &str) {
+        if let Some(element) = link.and_then(|index| self.upstream(index)) {
+            let completions = element
+                .get_caps_completions(&tail[prefix.len()..])
+                .into_iter()
+                .map(|x| format!("{}{}", prefix, x))
+                .collect();
+
+            self.suggest(trim_to_word(tail, self.current_word, completions));
+        }
+    }
+
+    /// Completes the pad of the `element.pad` reference being typed at
+    /// `index`, a src pad unless it is the target of a link.
+    fn suggest_pads(&self, index: usize) {
+        let pad = match &self.pipeline.nodes[index] {
+            Node::PadRef(pad) => pad,
+            _ => return,
+        };
+
+        let factory = match pad.element {
+            Some(name) => self.names.get(name).copied(),
+            None => match self.pipeline.nodes[..index].last() {
+                Some(Node::Element(element)) => Some(element.name),
+                _ => None,
+            },
+        };
+
+        let direction = if self.pipeline.link_before(index).is_some() {
+            gstreamer::PadDirection::Sink
+        } else {
+            gstreamer::PadDirection::Src
+        };
+
+        let used = self
+            .pipeline
+            .nodes
+            .iter()
+            .chain(self.after.nodes.iter())
+            .filter_map(|node| match node {
+                Node::PadRef(x) if x.element == pad.element && x.span != pad.span => x.pad,
+                _ => None,
+            })
+            .collect::<Vec<&str>>();
+
+        if let Some(factory) = factory {
+            let tail = &self.args[pad.span.start..];
+            let (prefix, typed) = tail.split_at(tail.len() - pad.pad.unwrap_or("").len());
+
+            // Only the sink pads the upstream caps can go into.
+            let pads = match self
+                .pipeline
+                .link_before(index)
+                .and_then(|x| self.upstream(x))
+            {
+                Some(upstream) => upstream.get_sink_pad_names(factory, &used),
+                None => gstreamer::get_pad_names(factory, direction, &used),
             };
 
-            if let Some(element) = gstreamer::find_element(prev_elem, prev_pad) {
-                element.get_compatible_elements(current_word).suggest();
+            let completions = pads
+                .into_iter()
+                .filter(|x| x.starts_with(typed))
+                .map(|x| format!("{}{}", prefix, x))
+                .collect();
+
+            self.suggest(trim_to_word(tail, self.current_word, completions));
+        }
+    }
+
+    /// Completes the value of the property being typed as its spec calls for:
+    /// paths, URIs, caps or one of a set of values. Returns false unless the
+    /// remainder is an unfinished `prop=value` of the last element.
+    fn complete_value(&self, rem: &str) -> bool {
+        let element = match self.pipeline.nodes.last() {
+            Some(Node::Element(element)) => element,
+            _ => return false,
+        };
+
+        let (name, value) = match split_property(rem) {
+            Some(property) => property,
+            None => return false,
+        };
+
+        let value = value.trim_start_matches(&['"', '\''][..]);
+        let prefix = &rem[..rem.len() - value.len()];
+
+        let gst_element = match gstreamer::find_element(element.name, None) {
+            Some(gst_element) => gst_element,
+            None => return true,
+        };
+
+        let info = match gst_element.get_property(name) {
+            Some(info) => info,
+            None => return true,
+        };
+
+        let values = match &info.kind {
+            ValueKind::Path => complete_path(value),
+            ValueKind::Uri => match value.split_once("://") {
+                Some(("file", path)) => complete_path(path)
+                    .into_iter()
+                    .map(|x| format!("file://{}", x))
+                    .collect(),
+                Some(_) => vec![],
+                None => gst_element
+                    .get_uri_protocols()
+                    .into_iter()
+                    .map(|x| format!("{}://", x))
+                    .filter(|x| x.starts_with(value))
+                    .collect(),
+            },
+            ValueKind::Caps => {
+                let len = self.pipeline.nodes.len();
+                self.suggest_caps(self.pipeline.link_before(len - 1), rem, prefix);
+                return true;
+            }
+            // A pipeline description of its own, with its own names. Bash
+            // replaces all of it, so what's before the word goes in front.
+            ValueKind::Element => {
+                let (head, word) = split_nested(value);
+                let names = parser::declarations(value);
+
+                self.suggest(
+                    complete(value, "", names, word)
+                        .into_iter()
+                        .map(|x| format!("{}{}", head, x))
+                        .collect(),
+                );
+                return true;
+            }
+            // Shown, never inserted: the two entries have nothing in common
+            // for bash to put in place of the value typed.
+            ValueKind::Number(range) => {
+                let bounds = if value.is_empty() || range.contains(value) {
+                    format!("range {}", range)
+                } else {
+                    format!("out of range {}", range)
+                };
+
+                self.suggest(vec![format!("default {}", range.default), bounds]);
+                return true;
+            }
+            kind => kind.complete(value),
+        };
+
+        let values = values
+            .into_iter()
+            .map(|x| format!("{}{}", prefix, x))
+            .collect();
+
+        self.suggest(trim_to_word(rem, self.current_word, values));
+        true
+    }
+
+    /// Picks out an inline caps filter under the cursor. Returns false if
+    /// there is none.
+    fn complete_caps(&self, rem: &str) -> bool {
+        let len = self.pipeline.nodes.len();
+
+        match self.pipeline.nodes.last() {
+            Some(Node::Caps(caps)) if rem.is_empty() && caps.span.end == self.args.len() => {
+                let tail = &self.args[caps.span.start..];
+                let prefix = &tail[..tail.len() - tail.trim_start_matches(&['"', '\''][..]).len()];
+
+                self.suggest_caps(self.pipeline.link_before(len - 1), tail, prefix);
             }
-        } else if let Some(element) = gstreamer::find_element(parsed[i as usize].0, None) {
-            if parsed[i as usize].2.is_some() {
-                return;
+            Some(Node::Link(_)) if rem.contains('/') => {
+                let prefix = &rem[..rem.len() - rem.trim_start_matches(&['"', '\''][..]).len()];
+
+                self.suggest_caps(Some(len - 1), rem, prefix);
             }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Suggests what comes next, `rem` being what's left unparsed and `bin`
+    /// the kind and properties of a bin just opened.
+    fn complete(&self, rem: &str, bin: Option<(&str, Vec<&str>)>) {
+        if self.complete_caps(rem) || self.complete_value(rem) {
+            return;
+        }
 
-            let arr = parsed[i as usize]
-                .1
+        if !is_remainder_sane(rem) {
+            return;
+        }
+
+        let len = self.pipeline.nodes.len();
+        let current_word = self.current_word;
+
+        if let Some((kind, arr)) = bin {
+            if let Some(gst_element) = gstreamer::find_element(kind, None) {
+                self.suggest(gst_element.get_property_names(&arr, current_word));
+            }
+
+            return self.suggest_elements(self.pipeline.link_before(len));
+        }
+
+        match self.pipeline.nodes.last() {
+            None => self.suggest_elements(None),
+            Some(Node::Link(_)) => self.suggest_elements(Some(len - 1)),
+            Some(Node::Element(element))
+                if element.properties.is_empty() && current_word == Some(element.name) =>
+            {
+                self.suggest_elements(self.pipeline.link_before(len - 1))
+            }
+            Some(Node::Element(element)) => {
+                if let Some(gst_element) = gstreamer::find_element(element.name, None) {
+                    let arr = element
+                        .properties
+                        .iter()
+                        .map(|x| x.name)
+                        .chain(self.following.iter().copied())
+                        .collect::<Vec<&str>>();
+
+                    // `child::property` names break the word at the colons.
+                    let names = gst_element.get_property_names(&arr, current_word);
+                    self.suggest(trim_to_word(
+                        current_word.unwrap_or(""),
+                        current_word,
+                        names,
+                    ));
+                }
+            }
+            Some(Node::PadRef(pad)) if pad.span.end == self.args.len() => {
+                self.suggest_pads(len - 1)
+            }
+            Some(Node::PadRef(_)) | Some(Node::Caps(_)) | Some(Node::Bin(_)) => {}
+        }
+    }
+}
+
+/// What comes next after `args`, a pipeline description up to the cursor,
+/// `after` being the rest of it past the word under the cursor.
+fn complete<'a>(
+    args: &'a str,
+    after: &'a str,
+    names: HashMap<&'a str, &'a str>,
+    current_word: Option<&'a str>,
+) -> Vec<String> {
+    let (rem, pipeline) = match parser::parse(args) {
+        Ok(parsed) => parsed,
+        Err(_) => return vec![],
+    };
+
+    let (following, after) = parser::following(after);
+
+    // A bin just opened takes its own properties before the first element.
+    let bin = pipeline
+        .current_bin()
+        .filter(|bin| bin.pipeline.nodes.is_empty())
+        .map(|bin| {
+            let arr = bin
+                .properties
                 .iter()
-                .map(|x| x.0)
+                .chain(following.iter())
+                .map(|x| x.name)
                 .collect::<Vec<&str>>();
+            (bin.kind.unwrap_or("bin"), arr)
+        });
+
+    let ctx = Context {
+        args,
+        pipeline: pipeline.flatten(),
+        following: following.iter().map(|x| x.name).collect(),
+        after: after.flatten(),
+        names,
+        current_word,
+        suggestions: RefCell::new(vec![]),
+    };
+
+    ctx.complete(rem, bin);
 
-            element.get_property_names(&arr, current_word).suggest();
+    match current_word {
+        Some(word) if fuzzy::enabled() => {
+            fuzzy::keep_word(&word[word_start(word)..], ctx.suggestions.into_inner())
         }
+        _ => ctx.suggestions.into_inner(),
+    }
+}
+
+/// Splits `line` at the cursor, `point` characters in or at the end if none:
+/// the args before it, past the program name, what follows it but the rest
+/// of the word under it, and the whole line past the program name. None with
+/// the cursor on the program name.
+fn split_line<'a>(
+    line: &'a str,
+    point: Option<usize>,
+    current_word: Option<&str>,
+) -> Option<(&'a str, &'a str, &'a str)> {
+    let point = point
+        .and_then(|x| line.char_indices().nth(x))
+        .map_or(line.len().saturating_sub(1), |(i, _)| i);
+
+    let trimmed = line.trim_start();
+    let program = line.len() - trimmed.len() + trimmed.find(char::is_whitespace).unwrap_or(0);
+    let args = line.get(program..point)?.trim_start();
+
+    // The rest of the word under the cursor is replaced, not followed.
+    let after = match current_word {
+        Some(_) => line[point..].trim_start_matches(|c: char| !c.is_whitespace()),
+        None => &line[point..],
+    };
+
+    Some((args, after, &line[program..]))
+}
+
+/// Completes the line bash hands over in the environment.
+fn run() -> Vec<String> {
+    let input = match BashCompletionInput::from_env() {
+        Ok(input) => input,
+        Err(_) => return vec![],
+    };
+
+    let current_word = if input.current_word().is_empty() {
+        None
+    } else {
+        Some(input.current_word())
+    };
+
+    // The raw line keeps the quoting and spacing the args lose, and has what
+    // follows the cursor too. The space ends a property at the end of it.
+    let line = format!("{} ", std::env::var("COMP_LINE").unwrap_or_default());
+    let point = std::env::var("COMP_POINT")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok());
+
+    let (args, after, rest) = match split_line(&line, point, current_word) {
+        Some(split) => split,
+        None => return vec![],
+    };
+
+    let names = parser::declarations(rest);
+
+    complete(args, after, names, current_word)
+}
+
+fn main() {
+    let argv = std::env::args().collect::<Vec<_>>();
+    let command = argv.get(1).map(String::as_str);
+
+    // A running daemon has the registry loaded already.
+    if command != Some("plan") && command != Some("daemon") {
+        if let Some(suggestions) = daemon::request() {
+            return suggestions.suggest();
+        }
+    }
+
+    if !gstreamer::init() {
+        return;
+    }
+
+    match command {
+        Some("plan") => match &argv[2..] {
+            [source, sink] => {
+                for pipeline in gstreamer::plan(source, sink) {
+                    println!("{}", pipeline);
+                }
+            }
+            _ => eprintln!(
+                "usage: {} plan <source-element-or-file> <sink-element>",
+                argv[0]
+            ),
+        },
+        Some("daemon") => daemon::serve(run),
+        _ => run().suggest(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse;
+    use super::*;
 
     #[test]
     fn test0() {
         assert_eq!(
-            parse("! "),
-            (
-                0i8,
-                Err(nom::Err::Error(nom::error::Error {
-                    input: "",
-                    code: nom::error::ErrorKind::IsNot
-                }))
-            )
+            split_property("location= /tmp/a"),
+            Some(("location", "/tmp/a"))
         );
+        assert_eq!(split_property("is-live = "), Some(("is-live", "")));
+        assert_eq!(split_property("video/x-raw,format="), None);
+        assert_eq!(split_property("location"), None);
     }
 
     #[test]
     fn test1() {
+        let values = vec!["pattern=smpte".to_owned(), "pattern=snow".to_owned()];
         assert_eq!(
-            parse("! filesrc "),
-            (0, Ok(("", vec![("filesrc", vec![], None)])))
+            trim_to_word("pattern=s", Some("pattern=s"), values),
+            vec!["smpte", "snow"]
         );
-    }
-
-    #[test]
-    fn test2() {
         assert_eq!(
-            parse("! filesrc ! fakesink "),
-            (
-                1,
-                Ok((
-                    "",
-                    vec![("filesrc", vec![], None), ("fakesink", vec![], None)]
-                ))
-            )
+            trim_to_word(
+                "pattern = s",
+                Some("s"),
+                vec!["pattern = smpte".to_owned(), "pattern = snow".to_owned()]
+            ),
+            vec!["smpte", "snow"]
         );
     }
 
     #[test]
-    fn test3() {
-        assert_eq!(
-            parse("! filesrc ! fakesink ! "),
-            (
-                2,
-                Ok((
-                    "! ",
-                    vec![("filesrc", vec![], None), ("fakesink", vec![], None)]
-                ))
-            )
-        );
-    }
+    fn test2() {
+        assert_eq!(open_quote("caps=\"video/x-raw, format=NV"), Some(5));
+        assert_eq!(open_quote("caps=\"video/x-raw\" ! 'a"), Some(21));
+        assert_eq!(open_quote("caps=\"it's\""), None);
 
-    #[test]
-    fn test4() {
         assert_eq!(
-            parse("! filesrc ! fakesink !"),
-            (
-                1,
-                Ok((
-                    "!",
-                    vec![("filesrc", vec![], None), ("fakesink", vec![], None)]
-                ))
-            )
+            trim_to_word(
+                "caps=\"video/x-raw, format=NV",
+                Some("format=NV"),
+                vec!["caps=\"video/x-raw, format=NV12".to_owned()]
+            ),
+            vec!["video/x-raw, format=NV12"]
         );
-    }
-
-    #[test]
-    fn test5() {
         assert_eq!(
-            parse("! filesrc ! fakesink name=abc test="),
-            (
-                1,
-                Ok((
-                    "test=",
-                    vec![
-                        ("filesrc", vec![], None),
-                        ("fakesink", vec![("name", "abc")], None)
-                    ]
-                ))
-            )
+            trim_to_word(
+                "\"audio/x-raw, rate=",
+                Some("rate="),
+                vec!["\"audio/x-raw, rate=44100".to_owned()]
+            ),
+            vec!["audio/x-raw, rate=44100"]
         );
-    }
-
-    #[test]
-    fn test6() {
         assert_eq!(
-            parse("! filesrc ! fakesink name=abc test =   "),
-            (
-                1,
-                Ok((
-                    "test =   ",
-                    vec![
-                        ("filesrc", vec![], None),
-                        ("fakesink", vec![("name", "abc")], None)
-                    ]
-                ))
-            )
+            trim_to_word(
+                "location='/tmp/my vi",
+                Some("vi"),
+                vec!["location='/tmp/my video.mp4".to_owned()]
+            ),
+            vec!["/tmp/my video.mp4"]
         );
     }
 
     #[test]
-    fn test7() {
-        assert_eq!(
-            parse("! filesrc ! fakesink name=abc test = \" random=  "),
-            (
-                1,
-                Ok((
-                    "test = \" random=  ",
-                    vec![
-                        ("filesrc", vec![], None),
-                        ("fakesink", vec![("name", "abc")], None)
-                    ]
-                ))
-            )
-        );
-    }
+    fn test3() {
+        let line = "gst-launch-1.0 videotestsrc ! x264enc ! mp4mux ! filesink ";
 
-    #[test]
-    fn test8() {
         assert_eq!(
-            parse("! filesrc ! fakesink name=abc test =  random=  "),
-            (
-                1,
-                Ok((
-                    "",
-                    vec![
-                        ("filesrc", vec![], None),
-                        ("fakesink", vec![("name", "abc"), ("test", "random=")], None)
-                    ]
-                ))
-            )
+            split_line(line, Some(30), None),
+            Some((
+                "videotestsrc ! ",
+                "x264enc ! mp4mux ! filesink ",
+                &line[14..]
+            ))
         );
-    }
-
-    #[test]
-    fn test9() {
         assert_eq!(
-            parse("! filesrc ! fakesink name=abc test =  random=  value"),
-            (
-                1,
-                Ok((
-                    "value",
-                    vec![
-                        ("filesrc", vec![], None),
-                        ("fakesink", vec![("name", "abc"), ("test", "random=")], None)
-                    ]
-                ))
-            )
+            split_line(line, Some(33), Some("x26")),
+            Some(("videotestsrc ! x26", " ! mp4mux ! filesink ", &line[14..]))
         );
+        assert_eq!(split_line(line, Some(5), Some("gst-l")), None);
     }
 
     #[test]
-    fn test10() {
+    fn test4() {
+        // The cursor at the end is before the space added to the line.
         assert_eq!(
-            parse("! filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt qt.video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10 "),
-            (
-                3,
-                Ok((
-                    "",
-                    vec![
-                        ("filesrc", vec![("name", "fsrc"), ("location", "/tmp/video.mp4"), ("prop", "2")], None),
-                        ("qtdemux", vec![("name", "qt")], Some((Some("qt"), Some("video_0")))),
-                        ("ffdec_mpeg4", vec![], None),
-                        ("videosink", vec![("name", "abc"), ("test", "10")], None)
-                    ]
-                ))
-            )
+            split_line("  gst-launch-1.0 fakesrc is-live=true ", None, None),
+            Some(("fakesrc is-live=true", " ", " fakesrc is-live=true "))
         );
-    }
 
-    #[test]
-    fn test11() {
+        // Bash counts characters, not bytes.
+        let line = "gst-launch-1.0 filesrc location=é.mp4 ! fakesink ";
         assert_eq!(
-            parse("! filesrc name=fsrc location= \"/tmp/my video.mp4\" prop = 2 ! qtdemux name=qt qt. !  ffdec_mpeg4 ! videosink name=abc test = 10   "),
-            (
-                3,
-                Ok((
-                    "",
-                    vec![
-                        ("filesrc", vec![("name", "fsrc"), ("location", "/tmp/my video.mp4"), ("prop", "2")], None),
-                        ("qtdemux", vec![("name", "qt")], Some((Some("qt"), None))),
-                        ("ffdec_mpeg4", vec![], None),
-                        ("videosink", vec![("name", "abc"), ("test", "10")], None)
-                    ]
-                ))
-            )
+            split_line(line, Some(40), None),
+            Some(("filesrc location=é.mp4 ! ", "fakesink ", &line[14..]))
         );
     }
 
     #[test]
-    fn test12() {
+    fn test5() {
         assert_eq!(
-            parse("! filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt .video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10"),
-            (
-                3,
-                Ok((
-                    "test = 10",
-                    vec![
-                        ("filesrc", vec![("name", "fsrc"), ("location", "/tmp/video.mp4"), ("prop", "2")], None),
-                        ("qtdemux", vec![("name", "qt")], Some((None, Some("video_0")))),
-                        ("ffdec_mpeg4", vec![], None),
-                        ("videosink", vec![("name", "abc")], None)
-                    ]
-                ))
-            )
+            split_nested("videoconvert ! autovi"),
+            ("videoconvert ! ", Some("autovi"))
         );
-    }
-
-    #[test]
-    fn test13() {
+        assert_eq!(split_nested("videoconvert ! "), ("videoconvert ! ", None));
         assert_eq!(
-            parse("! filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10   "),
-            (
-                1,
-                Ok((
-                    "video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10   ",
-                    vec![
-                        ("filesrc", vec![("name", "fsrc"), ("location", "/tmp/video.mp4"), ("prop", "2")], None),
-                        ("qtdemux", vec![("name", "qt")], None),
-                    ]
-                ))
-            )
+            split_nested("videotestsrc pattern=s"),
+            ("videotestsrc pattern=", Some("pattern=s"))
         );
     }
 }
@@ -0,0 +1,1185 @@
+use nom::branch::alt;
+use nom::bytes::complete::{is_not, tag};
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{consumed, eof, map, opt, peek, recognize, verify};
+use nom::multi::{many0, separated_list1};
+use nom::sequence::{delimited, pair, preceded, terminated, tuple};
+use nom::{IResult, Offset};
+use std::collections::HashMap;
+
+/// Byte range of a node in the parsed command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// `name=value`, the value stripped of its quotes.
+#[derive(Debug, PartialEq)]
+pub struct Property<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    pub span: Span,
+}
+
+/// An element factory name and the properties set on it.
+#[derive(Debug, PartialEq)]
+pub struct Element<'a> {
+    pub name: &'a str,
+    pub properties: Vec<Property<'a>>,
+    pub span: Span,
+}
+
+impl<'a> Element<'a> {
+    pub fn property(&self, name: &str) -> Option<&Property<'a>> {
+        self.properties.iter().find(|prop| prop.name == name)
+    }
+}
+
+/// `element.pad`, either side may be omitted but not both.
+#[derive(Debug, PartialEq)]
+pub struct PadRef<'a> {
+    pub element: Option<&'a str>,
+    pub pad: Option<&'a str>,
+    pub span: Span,
+}
+
+/// `field=value` of a caps structure, the value kept verbatim including any
+/// `(type)` cast, range or list.
+#[derive(Debug, PartialEq)]
+pub struct CapsField<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+    pub span: Span,
+}
+
+/// `media/type(features),field=value,...`
+#[derive(Debug, PartialEq)]
+pub struct CapsStructure<'a> {
+    pub media_type: &'a str,
+    pub features: Option<&'a str>,
+    pub fields: Vec<CapsField<'a>>,
+    pub span: Span,
+}
+
+/// An inline caps filter, `text` is the unquoted caps string as GStreamer
+/// would parse it.
+#[derive(Debug, PartialEq)]
+pub struct CapsFilter<'a> {
+    pub text: &'a str,
+    pub structures: Vec<CapsStructure<'a>>,
+    pub span: Span,
+}
+
+/// `( ... )` or `type.( ... )`, `closed` is false while the closing
+/// parenthesis is yet to be typed.
+#[derive(Debug, PartialEq)]
+pub struct Bin<'a> {
+    pub kind: Option<&'a str>,
+    pub properties: Vec<Property<'a>>,
+    pub pipeline: Pipeline<'a>,
+    pub closed: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Node<'a> {
+    Element(Element<'a>),
+    PadRef(PadRef<'a>),
+    Caps(CapsFilter<'a>),
+    Bin(Bin<'a>),
+    Link(Span),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Pipeline<'a> {
+    pub nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Factory of every element or bin declared with `name=`, keyed by name.
+    pub fn names(&self) -> HashMap<&'a str, &'a str> {
+        let mut names = HashMap::new();
+        self.collect_names(&mut names);
+        names
+    }
+
+    fn collect_names(&self, names: &mut HashMap<&'a str, &'a str>) {
+        for node in self.nodes.iter() {
+            match node {
+                Node::Element(element) => {
+                    if let Some(prop) = element.property("name") {
+                        names.insert(prop.value, element.name);
+                    }
+                }
+                Node::Bin(bin) => {
+                    if let Some(prop) = bin.properties.iter().find(|prop| prop.name == "name") {
+                        names.insert(prop.value, bin.kind.unwrap_or("bin"));
+                    }
+
+                    bin.pipeline.collect_names(names);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Innermost bin left open at the end of the line.
+    pub fn current_bin(&self) -> Option<&Bin<'a>> {
+        match self.nodes.last() {
+            Some(Node::Bin(bin)) if !bin.closed => bin.pipeline.current_bin().or(Some(bin)),
+            _ => None,
+        }
+    }
+
+    /// Opens up bins in place, so that a link into a bin goes to its first
+    /// element and a link out of it comes from its last one.
+    pub fn flatten(self) -> Pipeline<'a> {
+        let mut nodes = vec![];
+
+        for node in self.nodes {
+            match node {
+                Node::Bin(bin) => nodes.extend(bin.pipeline.flatten().nodes),
+                node => nodes.push(node),
+            }
+        }
+
+        Pipeline { nodes }
+    }
+
+    /// Index of the link right before the node at `index`.
+    pub fn link_before(&self, index: usize) -> Option<usize> {
+        index
+            .checked_sub(1)
+            .filter(|&i| matches!(self.nodes[i], Node::Link(_)))
+    }
+
+    /// Factory name and pad feeding the link at `index`, named references
+    /// looked up in `names`.
+    pub fn link_source(
+        &self,
+        index: usize,
+        names: &HashMap<&'a str, &'a str>,
+    ) -> Option<(&'a str, Option<&'a str>)> {
+        match self.nodes[..index].last()? {
+            Node::Element(element) => Some((element.name, None)),
+            Node::PadRef(PadRef {
+                element: Some(name),
+                pad,
+                ..
+            }) => names.get(name).map(|factory| (*factory, *pad)),
+            Node::PadRef(PadRef {
+                element: None, pad, ..
+            }) => match self.nodes[..index - 1].last()? {
+                Node::Element(element) => Some((element.name, *pad)),
+                _ => None,
+            },
+            Node::Caps(_) => match index.checked_sub(2) {
+                Some(link) if matches!(self.nodes[link], Node::Link(_)) => {
+                    self.link_source(link, names)
+                }
+                _ => None,
+            },
+            Node::Bin(bin) => bin.pipeline.link_source(bin.pipeline.nodes.len(), names),
+            Node::Link(_) => None,
+        }
+    }
+
+    /// Caps filters the link at `index` goes through, nearest first.
+    pub fn link_filters(&self, index: usize) -> Vec<&CapsFilter<'a>> {
+        let mut filters = vec![];
+        let mut index = index;
+
+        while let Some(Node::Caps(caps)) = self.nodes[..index].last() {
+            filters.push(caps);
+
+            match index.checked_sub(2) {
+                Some(link) if matches!(self.nodes[link], Node::Link(_)) => index = link,
+                _ => break,
+            }
+        }
+
+        filters
+    }
+}
+
+fn span(base: &str, from: &str, to: &str) -> Span {
+    Span::new(base.offset(from), base.offset(to))
+}
+
+fn is_ident(s: &str) -> bool {
+    s.chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A property name, `::`-separated for one set on a child of the element.
+fn is_property_name(s: &str) -> bool {
+    s.split("::").all(|x| !x.is_empty() && is_ident(x))
+}
+
+fn separator(s: &str) -> IResult<&str, Vec<&str>> {
+    many0(alt((
+        space1,
+        recognize(tuple((char('-'), is_not(" \t"), space0))),
+    )))(s)
+}
+
+fn link<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, Node<'a>> {
+    move |s| {
+        let (o, _) = terminated(char('!'), peek(space1))(s)?;
+        Ok((o, Node::Link(span(base, s, o))))
+    }
+}
+
+fn element<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, Element<'a>> {
+    move |s| {
+        let (o, name) = verify(is_not(" \t)"), is_ident)(s)?;
+        Ok((
+            o,
+            Element {
+                name,
+                properties: vec![],
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+fn property_value(s: &str) -> IResult<&str, &str> {
+    terminated(
+        alt((
+            delimited(
+                char('"'),
+                map(opt(is_not("\"")), Option::unwrap_or_default),
+                char('"'),
+            ),
+            delimited(
+                char('\''),
+                map(opt(is_not("\'")), Option::unwrap_or_default),
+                char('\''),
+            ),
+            verify(is_not(" \t"), |v: &str| {
+                !v.contains('\'') && !v.contains('"')
+            }),
+        )),
+        peek(space1),
+    )(s)
+}
+
+fn property<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, Property<'a>> {
+    move |s| {
+        let (o, (name, _, value)) = tuple((
+            verify(is_not("= \t"), is_property_name),
+            tuple((space0, char('='), space0)),
+            property_value,
+        ))(s)?;
+
+        Ok((
+            o,
+            Property {
+                name,
+                value,
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+fn pad_ref<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, PadRef<'a>> {
+    move |s| {
+        let (o, (element, pad)) = verify(
+            pair(
+                terminated(opt(is_not(" \t=.!()")), char('.')),
+                opt(is_not(" \t)")),
+            ),
+            |(element, pad): &(Option<&str>, Option<&str>)| element.is_some() || pad.is_some(),
+        )(s)?;
+
+        Ok((
+            o,
+            PadRef {
+                element,
+                pad,
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+fn caps_token(s: &str) -> IResult<&str, &str> {
+    verify(is_not(" \t,;/=()[]{}<>\"'"), |t: &str| {
+        t.chars()
+            .all(|c| c.is_alphanumeric() || "-_.+:".contains(c))
+    })(s)
+}
+
+fn caps_scalar(s: &str) -> IResult<&str, &str> {
+    alt((
+        recognize(delimited(char('"'), opt(is_not("\"")), char('"'))),
+        is_not(" \t,;[]{}<>\"'"),
+    ))(s)
+}
+
+fn caps_items<'a>(open: char, close: char) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    recognize(tuple((
+        char(open),
+        space0,
+        caps_value,
+        many0(tuple((space0, char(','), space0, caps_value))),
+        space0,
+        char(close),
+    )))
+}
+
+fn caps_value(s: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        opt(delimited(char('('), caps_token, char(')'))),
+        alt((
+            caps_items('[', ']'),
+            caps_items('{', '}'),
+            caps_items('<', '>'),
+            caps_scalar,
+        )),
+    ))(s)
+}
+
+fn caps_field<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, CapsField<'a>> {
+    move |s| {
+        let (o, (name, _, value)) =
+            tuple((caps_token, tuple((space0, char('='), space0)), caps_value))(s)?;
+
+        Ok((
+            o,
+            CapsField {
+                name,
+                value,
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+fn caps_structure<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, CapsStructure<'a>> {
+    move |s| {
+        let (o, (media_type, features, fields)) = tuple((
+            recognize(tuple((caps_token, char('/'), caps_token))),
+            opt(delimited(char('('), is_not(")"), char(')'))),
+            many0(preceded(
+                tuple((space0, char(','), space0)),
+                caps_field(base),
+            )),
+        ))(s)?;
+
+        Ok((
+            o,
+            CapsStructure {
+                media_type,
+                features,
+                fields,
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+fn caps_structures<'a>(
+    base: &'a str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Vec<CapsStructure<'a>>> {
+    separated_list1(tuple((space0, char(';'), space0)), caps_structure(base))
+}
+
+fn caps_filter<'a>(base: &'a str) -> impl FnMut(&'a str) -> IResult<&'a str, CapsFilter<'a>> {
+    move |s| {
+        let (o, (text, structures)) = terminated(
+            alt((
+                delimited(
+                    char('"'),
+                    consumed(delimited(space0, caps_structures(base), space0)),
+                    char('"'),
+                ),
+                delimited(
+                    char('\''),
+                    consumed(delimited(space0, caps_structures(base), space0)),
+                    char('\''),
+                ),
+                consumed(caps_structures(base)),
+            )),
+            peek(alt((space1, eof))),
+        )(s)?;
+
+        Ok((
+            o,
+            CapsFilter {
+                text: text.trim(),
+                structures,
+                span: span(base, s, o),
+            },
+        ))
+    }
+}
+
+/// `(` or `type.(` followed by the bin's own properties.
+fn bin_open<'a>(
+    base: &'a str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, (Option<&'a str>, Vec<Property<'a>>)> {
+    pair(
+        alt((
+            map(char('('), |_| None),
+            map(
+                terminated(verify(is_not(" \t.()"), is_ident), tag(".(")),
+                Some,
+            ),
+        )),
+        many0(preceded(separator, property(base))),
+    )
+}
+
+/// Parses as much of a gst-launch pipeline description as possible,
+/// leaving whatever is incomplete (usually the word being typed) in the
+/// remainder.
+pub fn parse(s: &str) -> IResult<&str, Pipeline<'_>> {
+    chain(s, s)
+}
+
+/// Parses nodes up to the end of the line, a `)` closing the enclosing bin or
+/// anything incomplete. A bin left open ends the parse with its remainder.
+fn chain<'a>(base: &'a str, s: &'a str) -> IResult<&'a str, Pipeline<'a>> {
+    let mut pipeline = Pipeline::default();
+    let mut input = s;
+
+    loop {
+        let (o, _) = separator(input)?;
+        input = o;
+
+        if let Ok((o, (kind, properties))) = bin_open(base)(input) {
+            let (o, inner) = chain(base, o)?;
+            let (o, closed) = opt(char(')'))(o)?;
+
+            pipeline.nodes.push(Node::Bin(Bin {
+                kind,
+                properties,
+                pipeline: inner,
+                closed: closed.is_some(),
+                span: span(base, input, o),
+            }));
+            input = o;
+
+            if closed.is_some() {
+                continue;
+            } else {
+                return Ok((input, pipeline));
+            }
+        }
+
+        // A pad linked into ends the chain, like `! mux.audio_0`.
+        let expects_element = match pipeline.nodes.as_slice() {
+            [] => true,
+            [.., Node::Link(_), Node::PadRef(_)] => true,
+            [.., last] => matches!(last, Node::Link(_) | Node::Bin(_)),
+        };
+
+        if expects_element {
+            if let Ok((o, caps)) = caps_filter(base)(input) {
+                pipeline.nodes.push(Node::Caps(caps));
+                input = o;
+                continue;
+            }
+
+            if let Ok((o, element)) = element(base)(input) {
+                pipeline.nodes.push(Node::Element(element));
+                input = o;
+                continue;
+            }
+        }
+
+        if let Ok((o, node)) = link(base)(input) {
+            pipeline.nodes.push(node);
+            input = o;
+            continue;
+        }
+
+        if let Some(Node::Element(element)) = pipeline.nodes.last_mut() {
+            if let Ok((o, prop)) = property(base)(input) {
+                element.span.end = prop.span.end;
+                element.properties.push(prop);
+                input = o;
+                continue;
+            }
+        }
+
+        if let Ok((o, pad)) = pad_ref(base)(input) {
+            pipeline.nodes.push(Node::PadRef(pad));
+            input = o;
+            continue;
+        }
+
+        return Ok((input, pipeline));
+    }
+}
+
+/// Names declared anywhere on the line, stepping over whatever doesn't parse,
+/// so that a reference resolves even before its declaration.
+pub fn declarations(s: &str) -> HashMap<&str, &str> {
+    let mut names = HashMap::new();
+    let mut input = s;
+
+    while let Ok((rem, pipeline)) = chain(input, input) {
+        pipeline.collect_names(&mut names);
+
+        // Nothing follows the last property on the line to complete it.
+        if let (Some(Node::Element(element)), Some(name)) =
+            (pipeline.flatten().nodes.last(), rem.strip_prefix("name="))
+        {
+            if !name.is_empty() && !name.contains(|c: char| c.is_whitespace()) {
+                names.insert(name, element.name);
+            }
+        }
+
+        match rem.trim_start().split_once(|c: char| c.is_whitespace()) {
+            Some((_, rest)) => input = rest,
+            None => break,
+        }
+    }
+
+    names
+}
+
+/// The line after the cursor: the properties that carry on the element
+/// before it, then the rest of the pipeline.
+pub fn following(s: &str) -> (Vec<Property<'_>>, Pipeline<'_>) {
+    let (o, properties) = many0(preceded(separator, property(s)))(s).unwrap_or((s, vec![]));
+    let pipeline = chain(s, o).map_or_else(|_| Pipeline::default(), |(_, x)| x);
+
+    (properties, pipeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element<'a>(name: &'a str, start: usize, properties: Vec<Property<'a>>) -> Node<'a> {
+        let end = properties
+            .last()
+            .map_or(start + name.len(), |prop| prop.span.end);
+
+        Node::Element(Element {
+            name,
+            properties,
+            span: Span::new(start, end),
+        })
+    }
+
+    fn prop<'a>(name: &'a str, value: &'a str, start: usize, end: usize) -> Property<'a> {
+        Property {
+            name,
+            value,
+            span: Span::new(start, end),
+        }
+    }
+
+    fn pad<'a>(
+        element: Option<&'a str>,
+        pad: Option<&'a str>,
+        start: usize,
+        end: usize,
+    ) -> Node<'a> {
+        Node::PadRef(PadRef {
+            element,
+            pad,
+            span: Span::new(start, end),
+        })
+    }
+
+    fn link<'a>(start: usize) -> Node<'a> {
+        Node::Link(Span::new(start, start + 1))
+    }
+
+    #[test]
+    fn test0() {
+        assert_eq!(parse(""), Ok(("", Pipeline { nodes: vec![] })));
+    }
+
+    #[test]
+    fn test1() {
+        assert_eq!(
+            parse("filesrc "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![element("filesrc", 0, vec![])]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test2() {
+        assert_eq!(
+            parse("filesrc ! fakesink "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test3() {
+        assert_eq!(
+            parse("filesrc ! fakesink ! "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![]),
+                        link(19)
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test4() {
+        assert_eq!(
+            parse("filesrc ! fakesink !"),
+            Ok((
+                "!",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test5() {
+        assert_eq!(
+            parse("filesrc ! fakesink name=abc test="),
+            Ok((
+                "test=",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![prop("name", "abc", 19, 27)])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test6() {
+        assert_eq!(
+            parse("filesrc ! fakesink name=abc test =   "),
+            Ok((
+                "test =   ",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![prop("name", "abc", 19, 27)])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test7() {
+        assert_eq!(
+            parse("filesrc ! fakesink name=abc test = \" random=  "),
+            Ok((
+                "test = \" random=  ",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element("fakesink", 10, vec![prop("name", "abc", 19, 27)])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test8() {
+        assert_eq!(
+            parse("filesrc ! fakesink name=abc test =  random=  "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element(
+                            "fakesink",
+                            10,
+                            vec![prop("name", "abc", 19, 27), prop("test", "random=", 28, 43)]
+                        )
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test9() {
+        assert_eq!(
+            parse("filesrc ! fakesink name=abc test =  random=  value"),
+            Ok((
+                "value",
+                Pipeline {
+                    nodes: vec![
+                        element("filesrc", 0, vec![]),
+                        link(8),
+                        element(
+                            "fakesink",
+                            10,
+                            vec![prop("name", "abc", 19, 27), prop("test", "random=", 28, 43)]
+                        )
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test10() {
+        assert_eq!(
+            parse("filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt qt.video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10 "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element(
+                            "filesrc",
+                            0,
+                            vec![
+                                prop("name", "fsrc", 8, 17),
+                                prop("location", "/tmp/video.mp4", 18, 42),
+                                prop("prop", "2", 43, 49)
+                            ]
+                        ),
+                        link(50),
+                        element("qtdemux", 52, vec![prop("name", "qt", 60, 67)]),
+                        pad(Some("qt"), Some("video_0"), 68, 78),
+                        link(79),
+                        element("ffdec_mpeg4", 82, vec![]),
+                        link(94),
+                        element(
+                            "videosink",
+                            96,
+                            vec![prop("name", "abc", 106, 114), prop("test", "10", 115, 124)]
+                        )
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test11() {
+        assert_eq!(
+            parse("filesrc name=fsrc location= \"/tmp/my video.mp4\" prop = 2 ! qtdemux name=qt qt. !  ffdec_mpeg4 ! videosink name=abc test = 10   "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element(
+                            "filesrc",
+                            0,
+                            vec![
+                                prop("name", "fsrc", 8, 17),
+                                prop("location", "/tmp/my video.mp4", 18, 47),
+                                prop("prop", "2", 48, 56)
+                            ]
+                        ),
+                        link(57),
+                        element("qtdemux", 59, vec![prop("name", "qt", 67, 74)]),
+                        pad(Some("qt"), None, 75, 78),
+                        link(79),
+                        element("ffdec_mpeg4", 82, vec![]),
+                        link(94),
+                        element(
+                            "videosink",
+                            96,
+                            vec![prop("name", "abc", 106, 114), prop("test", "10", 115, 124)]
+                        )
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test12() {
+        assert_eq!(
+            parse("filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt .video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10"),
+            Ok((
+                "test = 10",
+                Pipeline {
+                    nodes: vec![
+                        element(
+                            "filesrc",
+                            0,
+                            vec![
+                                prop("name", "fsrc", 8, 17),
+                                prop("location", "/tmp/video.mp4", 18, 42),
+                                prop("prop", "2", 43, 49)
+                            ]
+                        ),
+                        link(50),
+                        element("qtdemux", 52, vec![prop("name", "qt", 60, 67)]),
+                        pad(None, Some("video_0"), 68, 76),
+                        link(77),
+                        element("ffdec_mpeg4", 80, vec![]),
+                        link(92),
+                        element("videosink", 94, vec![prop("name", "abc", 104, 112)])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test13() {
+        assert_eq!(
+            parse("filesrc name=fsrc location= /tmp/video.mp4 prop=2 ! qtdemux name=qt video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10   "),
+            Ok((
+                "video_0 !  ffdec_mpeg4 ! videosink name=abc test = 10   ",
+                Pipeline {
+                    nodes: vec![
+                        element(
+                            "filesrc",
+                            0,
+                            vec![
+                                prop("name", "fsrc", 8, 17),
+                                prop("location", "/tmp/video.mp4", 18, 42),
+                                prop("prop", "2", 43, 49)
+                            ]
+                        ),
+                        link(50),
+                        element("qtdemux", 52, vec![prop("name", "qt", 60, 67)])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test14() {
+        let (_, pipeline) =
+            parse("filesrc ! qtdemux name=qt qt.video_0 ! queue ! fakesink").unwrap();
+
+        assert_eq!(
+            pipeline.link_source(1, &pipeline.names()),
+            Some(("filesrc", None))
+        );
+        assert_eq!(
+            pipeline.link_source(4, &pipeline.names()),
+            Some(("qtdemux", Some("video_0")))
+        );
+        assert_eq!(
+            pipeline.link_source(6, &pipeline.names()),
+            Some(("queue", None))
+        );
+    }
+
+    fn caps_nodes<'a>(pipeline: &'a Pipeline<'a>) -> Vec<&'a CapsFilter<'a>> {
+        pipeline
+            .nodes
+            .iter()
+            .filter_map(|node| match node {
+                Node::Caps(caps) => Some(caps),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test15() {
+        assert_eq!(
+            parse("videotestsrc ! video/x-raw,format=I420 ! x264enc "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element("videotestsrc", 0, vec![]),
+                        link(13),
+                        Node::Caps(CapsFilter {
+                            text: "video/x-raw,format=I420",
+                            structures: vec![CapsStructure {
+                                media_type: "video/x-raw",
+                                features: None,
+                                fields: vec![CapsField {
+                                    name: "format",
+                                    value: "I420",
+                                    span: Span::new(27, 38)
+                                }],
+                                span: Span::new(15, 38)
+                            }],
+                            span: Span::new(15, 38)
+                        }),
+                        link(39),
+                        element("x264enc", 41, vec![])
+                    ]
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test16() {
+        let (rem, pipeline) = parse("videotestsrc ! video/x-raw(memory:GLMemory),width=(int)640,framerate=[ 1/1, 30/1 ], format={ I420, NV12 } ! fakesink ").unwrap();
+        let caps = caps_nodes(&pipeline);
+
+        assert_eq!(rem, "");
+        assert_eq!(caps.len(), 1);
+        assert_eq!(caps[0].structures[0].media_type, "video/x-raw");
+        assert_eq!(caps[0].structures[0].features, Some("memory:GLMemory"));
+        assert_eq!(
+            caps[0].structures[0]
+                .fields
+                .iter()
+                .map(|field| (field.name, field.value))
+                .collect::<Vec<_>>(),
+            vec![
+                ("width", "(int)640"),
+                ("framerate", "[ 1/1, 30/1 ]"),
+                ("format", "{ I420, NV12 }")
+            ]
+        );
+    }
+
+    #[test]
+    fn test17() {
+        let (rem, pipeline) = parse(
+            "audiotestsrc ! \"audio/x-raw, rate=48000; audio/x-raw, rate=44100\" ! fakesink ",
+        )
+        .unwrap();
+        let caps = caps_nodes(&pipeline);
+
+        assert_eq!(rem, "");
+        assert_eq!(
+            caps[0].text,
+            "audio/x-raw, rate=48000; audio/x-raw, rate=44100"
+        );
+        assert_eq!(caps[0].structures.len(), 2);
+        assert_eq!(caps[0].span, Span::new(15, 65));
+        assert_eq!(
+            pipeline.link_source(3, &pipeline.names()),
+            Some(("audiotestsrc", None))
+        );
+    }
+
+    #[test]
+    fn test18() {
+        let (rem, pipeline) = parse("videotestsrc ! video/x-raw,format= ").unwrap();
+
+        assert_eq!(rem, "video/x-raw,format= ");
+        assert_eq!(
+            pipeline.nodes,
+            vec![element("videotestsrc", 0, vec![]), link(13)]
+        );
+    }
+
+    #[test]
+    fn test19() {
+        let (_, pipeline) =
+            parse("videotestsrc ! video/x-raw ! video/x-raw,format=NV12 ! ").unwrap();
+
+        assert_eq!(
+            pipeline.link_source(5, &pipeline.names()),
+            Some(("videotestsrc", None))
+        );
+        assert_eq!(
+            pipeline
+                .link_filters(5)
+                .iter()
+                .map(|caps| caps.text)
+                .collect::<Vec<_>>(),
+            vec!["video/x-raw,format=NV12", "video/x-raw"]
+        );
+        assert!(pipeline.link_filters(1).is_empty());
+    }
+
+    #[test]
+    fn test20() {
+        let (rem, pipeline) = parse("( videotestsrc ! queue ) ! autovideosink ").unwrap();
+
+        assert_eq!(rem, "");
+        assert_eq!(
+            pipeline.nodes,
+            vec![
+                Node::Bin(Bin {
+                    kind: None,
+                    properties: vec![],
+                    pipeline: Pipeline {
+                        nodes: vec![
+                            element("videotestsrc", 2, vec![]),
+                            link(15),
+                            element("queue", 17, vec![])
+                        ]
+                    },
+                    closed: true,
+                    span: Span::new(0, 24)
+                }),
+                link(25),
+                element("autovideosink", 27, vec![])
+            ]
+        );
+        assert_eq!(
+            pipeline.link_source(1, &pipeline.names()),
+            Some(("queue", None))
+        );
+    }
+
+    #[test]
+    fn test21() {
+        let (rem, pipeline) =
+            parse("videotestsrc ! pipeline.( name=p ( queue name=q ) ! videoconvert test=")
+                .unwrap();
+
+        assert_eq!(rem, "test=");
+
+        let bin = pipeline.current_bin().unwrap();
+        assert_eq!(bin.kind, Some("pipeline"));
+        assert_eq!(bin.properties, vec![prop("name", "p", 26, 32)]);
+        assert!(!bin.closed);
+        assert_eq!(pipeline.names().get("q"), Some(&"queue"));
+
+        let pipeline = pipeline.flatten();
+        assert_eq!(
+            pipeline.nodes,
+            vec![
+                element("videotestsrc", 0, vec![]),
+                link(13),
+                element("queue", 35, vec![prop("name", "q", 41, 47)]),
+                link(50),
+                element("videoconvert", 52, vec![])
+            ]
+        );
+        assert_eq!(
+            pipeline.link_source(1, &pipeline.names()),
+            Some(("videotestsrc", None))
+        );
+        assert_eq!(
+            pipeline.link_source(3, &pipeline.names()),
+            Some(("queue", None))
+        );
+    }
+
+    #[test]
+    fn test22() {
+        let (rem, pipeline) = parse("videotestsrc ! ( ").unwrap();
+
+        assert_eq!(rem, "");
+        assert!(pipeline.current_bin().unwrap().pipeline.nodes.is_empty());
+        assert_eq!(
+            pipeline.flatten().link_source(1, &HashMap::new()),
+            Some(("videotestsrc", None))
+        );
+    }
+
+    #[test]
+    fn test23() {
+        let line =
+            "t. ! queue ! fak  location=\"unfinished ( videotestsrc ! tee name=t ) mp4mux name=mux";
+        let (_, pipeline) = parse(line).unwrap();
+        let names = declarations(line);
+
+        assert_eq!(names.get("t"), Some(&"tee"));
+        assert_eq!(names.get("mux"), Some(&"mp4mux"));
+        assert_eq!(pipeline.link_source(1, &names), Some(("tee", None)));
+        assert_eq!(pipeline.link_source(1, &pipeline.names()), None);
+    }
+
+    #[test]
+    fn test24() {
+        let (rem, pipeline) = parse("compositor sink_0::xpos=10 sink_1:xpos=0 ! fakesink").unwrap();
+
+        assert_eq!(rem, "sink_1:xpos=0 ! fakesink");
+        assert_eq!(
+            pipeline.nodes,
+            vec![element(
+                "compositor",
+                0,
+                vec![prop("sink_0::xpos", "10", 11, 26)]
+            )]
+        );
+    }
+
+    #[test]
+    fn test25() {
+        let (properties, pipeline) = following(" is-live=true pattern=ball ! mux.video_0 ");
+
+        assert_eq!(
+            properties,
+            vec![
+                prop("is-live", "true", 1, 13),
+                prop("pattern", "ball", 14, 26)
+            ]
+        );
+        assert_eq!(
+            pipeline.nodes,
+            vec![
+                link(27),
+                Node::PadRef(PadRef {
+                    element: Some("mux"),
+                    pad: Some("video_0"),
+                    span: Span::new(29, 40),
+                })
+            ]
+        );
+
+        let (properties, pipeline) = following("! fakesink ");
+        assert!(properties.is_empty());
+        assert_eq!(
+            pipeline.nodes,
+            vec![link(0), element("fakesink", 2, vec![])]
+        );
+    }
+
+    #[test]
+    fn test26() {
+        assert_eq!(
+            parse("audiotestsrc ! voaacenc ! mux.audio_0  videotestsrc ! x264enc ! "),
+            Ok((
+                "",
+                Pipeline {
+                    nodes: vec![
+                        element("audiotestsrc", 0, vec![]),
+                        link(13),
+                        element("voaacenc", 15, vec![]),
+                        link(24),
+                        pad(Some("mux"), Some("audio_0"), 26, 37),
+                        element("videotestsrc", 39, vec![]),
+                        link(52),
+                        element("x264enc", 54, vec![]),
+                        link(62),
+                    ]
+                }
+            ))
+        );
+    }
+}
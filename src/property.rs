@@ -0,0 +1,497 @@
+use gst::glib;
+use gst::prelude::*;
+use gstreamer as gst;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Whether deprecated properties are suggested too, as
+/// `GST_COMPLETION_DEPRECATED=1` asks.
+fn show_deprecated() -> bool {
+    std::env::var("GST_COMPLETION_DEPRECATED").is_ok_and(|x| !x.is_empty() && x != "0")
+}
+
+/// Properties every element has that make no sense to set from gst-launch.
+const INTERNAL: [&str; 1] = ["parent"];
+
+/// A value of a numeric property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Fraction(i32, i32),
+}
+
+impl Number {
+    /// Parses `value` as the same kind of number.
+    fn parse_like(&self, value: &str) -> Option<Number> {
+        match self {
+            Number::Int(_) => value.parse().ok().map(Number::Int),
+            Number::UInt(_) => value.parse().ok().map(Number::UInt),
+            Number::Float(_) => value.parse().ok().map(Number::Float),
+            Number::Fraction(..) => {
+                let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+                Some(Number::Fraction(num.parse().ok()?, den.parse().ok()?))
+            }
+        }
+    }
+
+    /// The number with its kind as the first letter, for the cache.
+    fn to_cache(self) -> String {
+        match self {
+            Number::Int(x) => format!("i{}", x),
+            Number::UInt(x) => format!("u{}", x),
+            Number::Float(x) => format!("f{}", x),
+            Number::Fraction(num, den) => format!("q{}/{}", num, den),
+        }
+    }
+
+    fn from_cache(s: &str) -> Option<Number> {
+        let (kind, value) = s.split_at(s.char_indices().nth(1)?.0);
+
+        let like = match kind {
+            "i" => Number::Int(0),
+            "u" => Number::UInt(0),
+            "f" => Number::Float(0.0),
+            "q" => Number::Fraction(0, 1),
+            _ => return None,
+        };
+
+        like.parse_like(value)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(a), Number::Int(b)) => a.partial_cmp(b),
+            (Number::UInt(a), Number::UInt(b)) => a.partial_cmp(b),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(b),
+            (Number::Fraction(an, ad), Number::Fraction(bn, bd)) if *ad > 0 && *bd > 0 => {
+                (i64::from(*an) * i64::from(*bd)).partial_cmp(&(i64::from(*bn) * i64::from(*ad)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Int(x) => write!(f, "{}", x),
+            Number::UInt(x) => write!(f, "{}", x),
+            // Bounds like G_MAXDOUBLE would run to hundreds of digits.
+            Number::Float(x) if *x != 0.0 && (x.abs() >= 1e9 || x.abs() < 1e-4) => {
+                write!(f, "{:e}", x)
+            }
+            Number::Float(x) => write!(f, "{}", x),
+            Number::Fraction(num, den) => write!(f, "{}/{}", num, den),
+        }
+    }
+}
+
+impl From<i32> for Number {
+    fn from(x: i32) -> Self {
+        Number::Int(x.into())
+    }
+}
+
+impl From<i64> for Number {
+    fn from(x: i64) -> Self {
+        Number::Int(x)
+    }
+}
+
+impl From<u32> for Number {
+    fn from(x: u32) -> Self {
+        Number::UInt(x.into())
+    }
+}
+
+impl From<u64> for Number {
+    fn from(x: u64) -> Self {
+        Number::UInt(x)
+    }
+}
+
+impl From<f32> for Number {
+    /// Goes by the shortest decimal for the `f32`, so that `0.1` doesn't
+    /// widen to `0.10000000149011612`.
+    fn from(x: f32) -> Self {
+        Number::Float(x.to_string().parse().unwrap_or_else(|_| x.into()))
+    }
+}
+
+impl From<f64> for Number {
+    fn from(x: f64) -> Self {
+        Number::Float(x)
+    }
+}
+
+impl From<gst::Fraction> for Number {
+    fn from(x: gst::Fraction) -> Self {
+        Number::Fraction(x.numer(), x.denom())
+    }
+}
+
+/// Bounds and default of a numeric property.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    pub min: Number,
+    pub max: Number,
+    pub default: Number,
+}
+
+impl Range {
+    fn new<T: Into<Number>>(min: T, max: T, default: T) -> Self {
+        Range {
+            min: min.into(),
+            max: max.into(),
+            default: default.into(),
+        }
+    }
+
+    /// Whether `value` is a number of the right kind within the bounds.
+    pub fn contains(&self, value: &str) -> bool {
+        self.default
+            .parse_like(value.trim())
+            .is_some_and(|x| self.min <= x && x <= self.max)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}..{}]", self.min, self.max)
+    }
+}
+
+/// What a property's value is, as far as completing it goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueKind {
+    /// A string naming a file.
+    Path,
+    /// A string URI.
+    Uri,
+    Caps,
+    /// A pipeline description, made into a bin by gst-launch.
+    Element,
+    /// Nicks and numeric values, the default first.
+    Enum(Vec<(String, i32)>),
+    /// Nicks, the ones set by default first.
+    Flags(Vec<String>),
+    /// The default.
+    Boolean(bool),
+    Number(Range),
+    Other,
+}
+
+impl ValueKind {
+    /// Enum, flags and boolean values starting with `prefix`. Enums complete
+    /// to their numeric values once a digit is typed, and flags one
+    /// `+`-joined nick at a time.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut values = match self {
+            ValueKind::Enum(values) => values
+                .iter()
+                .map(|(nick, value)| {
+                    if prefix.starts_with(|c: char| c.is_ascii_digit()) {
+                        value.to_string()
+                    } else {
+                        nick.clone()
+                    }
+                })
+                .filter(|x| x.starts_with(prefix))
+                .collect(),
+            ValueKind::Flags(values) => {
+                let (head, tail) = prefix.split_at(prefix.rfind('+').map_or(0, |i| i + 1));
+                let set = head.split('+').collect::<Vec<_>>();
+
+                values
+                    .iter()
+                    .filter(|x| x.starts_with(tail) && !set.contains(&x.as_str()))
+                    .map(|x| format!("{}{}", head, x))
+                    .collect()
+            }
+            ValueKind::Boolean(default) => [*default, !*default]
+                .iter()
+                .map(|x| x.to_string())
+                .filter(|x| x.starts_with(prefix))
+                .collect(),
+            _ => vec![],
+        };
+
+        values.dedup();
+        values
+    }
+}
+
+/// What completion needs to know about a property.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyInfo {
+    pub name: String,
+    pub kind: ValueKind,
+    pub writable: bool,
+    pub deprecated: bool,
+}
+
+impl PropertyInfo {
+    pub fn new(pspec: &glib::ParamSpec) -> Self {
+        let name = pspec.name().to_string();
+        let flags = pspec.flags();
+
+        PropertyInfo {
+            kind: value_kind(pspec, &name),
+            name,
+            writable: flags.contains(glib::ParamFlags::WRITABLE),
+            deprecated: flags.contains(glib::ParamFlags::DEPRECATED),
+        }
+    }
+
+    /// The property as a line of the cache: name, flags and kind, the kind
+    /// followed by what it holds, all separated by tabs.
+    pub fn to_cache(&self) -> String {
+        let flags = [(self.writable, 'w'), (self.deprecated, 'd')]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, c)| *c)
+            .collect::<String>();
+
+        let mut fields = vec![self.name.clone(), flags];
+
+        match &self.kind {
+            ValueKind::Path => fields.push("path".into()),
+            ValueKind::Uri => fields.push("uri".into()),
+            ValueKind::Caps => fields.push("caps".into()),
+            ValueKind::Element => fields.push("element".into()),
+            ValueKind::Enum(values) => {
+                fields.push("enum".into());
+                fields.extend(values.iter().map(|(nick, x)| format!("{}={}", nick, x)));
+            }
+            ValueKind::Flags(values) => {
+                fields.push("flags".into());
+                fields.extend(values.iter().cloned());
+            }
+            ValueKind::Boolean(default) => {
+                fields.push("boolean".into());
+                fields.push(default.to_string());
+            }
+            ValueKind::Number(range) => {
+                fields.push("number".into());
+                fields.extend(
+                    [range.min, range.max, range.default]
+                        .iter()
+                        .map(|x| x.to_cache()),
+                );
+            }
+            ValueKind::Other => fields.push("other".into()),
+        }
+
+        fields.join("\t")
+    }
+
+    pub fn from_cache(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let name = fields.next()?.to_owned();
+        let flags = fields.next()?;
+
+        let kind = match fields.next()? {
+            "path" => ValueKind::Path,
+            "uri" => ValueKind::Uri,
+            "caps" => ValueKind::Caps,
+            "element" => ValueKind::Element,
+            "enum" => ValueKind::Enum(
+                fields
+                    .map(|x| {
+                        let (nick, value) = x.rsplit_once('=')?;
+                        Some((nick.to_owned(), value.parse().ok()?))
+                    })
+                    .collect::<Option<_>>()?,
+            ),
+            "flags" => ValueKind::Flags(fields.map(str::to_owned).collect()),
+            "boolean" => ValueKind::Boolean(fields.next()?.parse().ok()?),
+            "number" => {
+                let mut numbers = fields.map(Number::from_cache);
+
+                ValueKind::Number(Range {
+                    min: numbers.next()??,
+                    max: numbers.next()??,
+                    default: numbers.next()??,
+                })
+            }
+            "other" => ValueKind::Other,
+            _ => return None,
+        };
+
+        Some(PropertyInfo {
+            name,
+            kind,
+            writable: flags.contains('w'),
+            deprecated: flags.contains('d'),
+        })
+    }
+
+    /// Whether the property is worth suggesting to set on the command line.
+    pub fn is_settable(&self) -> bool {
+        self.writable
+            && (!self.deprecated || show_deprecated())
+            && !INTERNAL.contains(&self.name.as_str())
+    }
+}
+
+fn value_kind(pspec: &glib::ParamSpec, name: &str) -> ValueKind {
+    if let Some(range) = range(pspec) {
+        ValueKind::Number(range)
+    } else if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecEnum>() {
+        let default = pspec.default_value_as_i32();
+        let mut values = pspec
+            .enum_class()
+            .values()
+            .iter()
+            .map(|x| (x.nick().to_string(), x.value()))
+            .collect::<Vec<_>>();
+
+        values.sort_by_key(|(_, value)| *value != default);
+        ValueKind::Enum(values)
+    } else if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecFlags>() {
+        let default = pspec.default_value_as_u32();
+        let mut values = pspec.flags_class().values().to_vec();
+
+        values.sort_by_key(|x| x.value() & default == 0);
+        ValueKind::Flags(values.iter().map(|x| x.nick().to_string()).collect())
+    } else if let Some(pspec) = pspec.downcast_ref::<glib::ParamSpecBoolean>() {
+        ValueKind::Boolean(pspec.default_value())
+    } else if pspec.value_type() == gst::Caps::static_type() {
+        ValueKind::Caps
+    } else if pspec.value_type().is_a(gst::Element::static_type()) {
+        ValueKind::Element
+    } else if pspec.value_type() != glib::Type::STRING {
+        ValueKind::Other
+    } else if name == "uri" || name.ends_with("-uri") {
+        ValueKind::Uri
+    } else if name == "location"
+        || name.ends_with("-location")
+        || name == "filename"
+        || name.ends_with("-filename")
+        || name.ends_with("-file")
+        || name.ends_with("-path")
+    {
+        ValueKind::Path
+    } else {
+        ValueKind::Other
+    }
+}
+
+fn range(pspec: &glib::ParamSpec) -> Option<Range> {
+    if let Some(x) = pspec.downcast_ref::<glib::ParamSpecInt>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecUInt>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecLong>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecULong>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecInt64>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecUInt64>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecFloat>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else if let Some(x) = pspec.downcast_ref::<glib::ParamSpecDouble>() {
+        Some(Range::new(x.minimum(), x.maximum(), x.default_value()))
+    } else {
+        pspec
+            .downcast_ref::<gst::ParamSpecFraction>()
+            .map(|x| Range::new(x.minimum(), x.maximum(), x.default_value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test0() {
+        let range = Range::new(1u32, 2048000, 2048);
+
+        assert_eq!(range.to_string(), "[1..2048000]");
+        assert!(range.contains("2048000"));
+        assert!(!range.contains("0"));
+        assert!(!range.contains("-1"));
+        assert!(!range.contains("1.5"));
+
+        let range = Range::new(
+            gst::Fraction::new(0, 1),
+            gst::Fraction::new(i32::MAX, 1),
+            gst::Fraction::new(30, 1),
+        );
+
+        assert!(range.contains("30000/1001"));
+        assert!(range.contains("25"));
+        assert!(!range.contains("-1/2"));
+
+        let range = Range::new(0.1f32, f32::MAX, 1.0);
+        assert_eq!(range.to_string(), "[0.1..3.4028235e38]");
+
+        let range = Range::new(0.0, f64::MAX, 0.5);
+        assert_eq!(range.to_string(), "[0..1.7976931348623157e308]");
+        assert!(range.contains("0.25"));
+    }
+
+    #[test]
+    fn test1() {
+        let flags = ValueKind::Flags(vec!["sync".into(), "async".into(), "drop".into()]);
+
+        assert_eq!(flags.complete(""), vec!["sync", "async", "drop"]);
+        assert_eq!(flags.complete("sync+"), vec!["sync+async", "sync+drop"]);
+        assert_eq!(flags.complete("sync+d"), vec!["sync+drop"]);
+
+        let enums = ValueKind::Enum(vec![("smpte".into(), 0), ("snow".into(), 1)]);
+
+        assert_eq!(enums.complete("s"), vec!["smpte", "snow"]);
+        assert_eq!(enums.complete("1"), vec!["1"]);
+        assert_eq!(ValueKind::Boolean(true).complete(""), vec!["true", "false"]);
+    }
+
+    #[test]
+    fn test2() {
+        let properties = [
+            PropertyInfo {
+                name: "num-buffers".into(),
+                kind: ValueKind::Number(Range::new(-1, i32::MAX, -1)),
+                writable: true,
+                deprecated: false,
+            },
+            PropertyInfo {
+                name: "framerate".into(),
+                kind: ValueKind::Number(Range::new(
+                    gst::Fraction::new(0, 1),
+                    gst::Fraction::new(i32::MAX, 1),
+                    gst::Fraction::new(30000, 1001),
+                )),
+                writable: true,
+                deprecated: true,
+            },
+            PropertyInfo {
+                name: "pattern".into(),
+                kind: ValueKind::Enum(vec![("smpte".into(), 0), ("snow".into(), 1)]),
+                writable: false,
+                deprecated: false,
+            },
+            PropertyInfo {
+                name: "volume".into(),
+                kind: ValueKind::Number(Range::new(0.0, 10.0, 1.0)),
+                writable: true,
+                deprecated: false,
+            },
+        ];
+
+        for property in properties.iter() {
+            assert_eq!(
+                PropertyInfo::from_cache(&property.to_cache()).as_ref(),
+                Some(property)
+            );
+        }
+
+        assert_eq!(PropertyInfo::from_cache("pattern\tw\tenum\tsmpte"), None);
+    }
+}